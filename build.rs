@@ -0,0 +1,24 @@
+use std::process::Command;
+
+fn main() {
+    // Re-embed migrations when a new one is added.
+    println!("cargo:rerun-if-changed=migrations");
+
+    // Embed the commit reported through INDEXED_BY_VERSION_COLUMN, unless
+    // the build provides TRUNKS_GIT_COMMIT itself.
+    println!("cargo:rerun-if-env-changed=TRUNKS_GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    if std::env::var_os("TRUNKS_GIT_COMMIT").is_some() {
+        return;
+    }
+    let output = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output();
+    if let Ok(output) = output {
+        if output.status.success() {
+            let commit = String::from_utf8_lossy(&output.stdout);
+            println!("cargo:rustc-env=TRUNKS_GIT_COMMIT={}", commit.trim());
+        }
+    }
+}
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS block_state_trunks (
+    id INTEGER PRIMARY KEY,
+    last_processed_block BIGINT NOT NULL
+);
+
+INSERT INTO block_state_trunks (id, last_processed_block)
+VALUES (1, 0)
+ON CONFLICT (id) DO NOTHING;
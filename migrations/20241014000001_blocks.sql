@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS blocks (
+    block_number BIGINT PRIMARY KEY,
+    block_hash TEXT NOT NULL,
+    block_timestamp BIGINT NOT NULL,
+    event_count INTEGER NOT NULL,
+    indexed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,9 @@
+-- Continuation token of the next page to fetch for a (contract, block) whose
+-- pagination was interrupted in streaming mode.
+CREATE TABLE IF NOT EXISTS page_cursors (
+    contract_address TEXT NOT NULL,
+    block_number BIGINT NOT NULL,
+    continuation_token TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (contract_address, block_number)
+);
@@ -0,0 +1,12 @@
+-- Events that were fetched but deliberately not applied, kept with their raw
+-- payload for investigation.
+CREATE TABLE IF NOT EXISTS failed_events (
+    id BIGSERIAL PRIMARY KEY,
+    contract_address TEXT NOT NULL,
+    block_number BIGINT,
+    transaction_hash TEXT NOT NULL,
+    keys TEXT[] NOT NULL,
+    data TEXT[] NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
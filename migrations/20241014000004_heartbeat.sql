@@ -0,0 +1,7 @@
+-- One row per indexer instance, touched on every loop iteration so liveness
+-- can be monitored with plain SQL.
+CREATE TABLE IF NOT EXISTS heartbeat (
+    instance_name TEXT PRIMARY KEY,
+    last_loop_at TIMESTAMPTZ NOT NULL,
+    last_processed_block BIGINT NOT NULL
+);
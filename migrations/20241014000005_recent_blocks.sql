@@ -0,0 +1,5 @@
+-- Rolling window of recently processed block hashes used to detect reorgs.
+CREATE TABLE IF NOT EXISTS recent_blocks (
+    block_number BIGINT PRIMARY KEY,
+    block_hash TEXT NOT NULL
+);
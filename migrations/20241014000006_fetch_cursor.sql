@@ -0,0 +1,4 @@
+-- Highest block whose events were fetched, which may run ahead of the
+-- committed last_processed_block.
+ALTER TABLE block_state_trunks
+    ADD COLUMN IF NOT EXISTS last_fetched_block BIGINT NOT NULL DEFAULT 0;
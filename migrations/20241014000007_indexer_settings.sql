@@ -0,0 +1,7 @@
+-- Runtime overrides for the tunable settings, read periodically by the
+-- indexer. Deleting a row reverts that setting to its environment value.
+CREATE TABLE IF NOT EXISTS indexer_settings (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,8 @@
+-- Ranges completed by the reindex-ranges subcommand, so a rerun of the same
+-- file skips them.
+CREATE TABLE IF NOT EXISTS processed_ranges (
+    from_block BIGINT NOT NULL,
+    to_block BIGINT NOT NULL,
+    completed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (from_block, to_block)
+);
@@ -0,0 +1,9 @@
+-- Contracts skipped by the main pass after failing the same block
+-- QUARANTINE_AFTER_FAILURES times. next_block is where their separate
+-- catch-up resumes.
+CREATE TABLE IF NOT EXISTS quarantined_contracts (
+    contract_address TEXT PRIMARY KEY,
+    next_block BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    quarantined_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,5 @@
+-- L1 acceptance of each indexed block. accepted_on_l1_at is when Trunks
+-- first saw the block accepted, not the L1 block time.
+ALTER TABLE blocks
+    ADD COLUMN IF NOT EXISTS accepted_on_l1 BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS accepted_on_l1_at TIMESTAMPTZ;
@@ -0,0 +1,8 @@
+-- Digest of the events each contract emitted in a recently processed
+-- block, rechecked each pass to detect reorgs without a hash chain.
+CREATE TABLE IF NOT EXISTS block_contents (
+    block_number BIGINT NOT NULL,
+    contract_address TEXT NOT NULL,
+    content_hash BIGINT NOT NULL,
+    PRIMARY KEY (block_number, contract_address)
+);
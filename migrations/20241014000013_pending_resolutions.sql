@@ -0,0 +1,11 @@
+-- Resolutions that matched no events row, held with BUFFER_ORPHAN_RESOLUTIONS
+-- until the row is inserted and they can be applied.
+CREATE TABLE IF NOT EXISTS pending_resolutions (
+    event_address TEXT PRIMARY KEY,
+    event_outcome SMALLINT NOT NULL,
+    event_timestamp BIGINT NOT NULL,
+    transaction_hash TEXT NOT NULL,
+    block_number BIGINT,
+    resolved_by TEXT,
+    buffered_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,13 @@
+-- Append-only hash chain of processed blocks, with DIGEST_LOG=table. Each
+-- digest covers the row's other fields and the previous row's digest.
+CREATE TABLE IF NOT EXISTS block_digests (
+    id BIGSERIAL PRIMARY KEY,
+    block_number BIGINT NOT NULL,
+    block_hash TEXT,
+    event_count BIGINT NOT NULL,
+    changes_hash TEXT NOT NULL,
+    prev_digest TEXT NOT NULL,
+    digest TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
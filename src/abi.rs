@@ -0,0 +1,308 @@
+use crate::layout::{EventLayout, FieldDecoder};
+use crate::Context;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use starknet::core::types::{BlockId, BlockTag, ContractClass, Felt, FunctionCall};
+use starknet::core::utils::get_selector_from_name;
+use starknet::providers::Provider;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+/// View functions proxies commonly expose their implementation through,
+/// tried in order. Some return a class hash, others a contract address.
+const PROXY_FUNCTIONS: [&str; 3] = [
+    "get_implementation_hash",
+    "get_implementation",
+    "implementation",
+];
+
+/// EventTimeout's data members as declared in the ABI of the class that
+/// emits it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AbiEvent {
+    class_hash: String,
+    members: Vec<Member>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Member {
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+/// EventTimeout definitions by contract, fetched from the chain on first
+/// encounter and, with `ABI_CACHE_PATH`, kept on disk across restarts.
+/// Contracts whose ABI has no usable EventTimeout are remembered as `None`
+/// for the session and decoded with the manual layout. A failed fetch isn't
+/// remembered.
+pub struct AbiCache {
+    path: Option<String>,
+    events: Mutex<HashMap<Felt, Option<AbiEvent>>>,
+}
+
+impl AbiCache {
+    pub fn new(path: Option<String>) -> Self {
+        let mut events = HashMap::new();
+        if let Some(path) = &path {
+            match fs::read_to_string(path) {
+                Ok(contents) => {
+                    match serde_json::from_str::<HashMap<String, AbiEvent>>(&contents) {
+                        Ok(stored) => {
+                            for (address, event) in stored {
+                                if let Ok(address) = Felt::from_hex(&address) {
+                                    events.insert(address, Some(event));
+                                }
+                            }
+                            info!("📜 Loaded {} cached ABI(s) from {}", events.len(), path);
+                        }
+                        Err(e) => warn!("⚠️ Ignoring unreadable ABI cache {}: {}", path, e),
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => warn!("⚠️ Failed to read ABI cache {}: {}", path, e),
+            }
+        }
+
+        AbiCache {
+            path,
+            events: Mutex::new(events),
+        }
+    }
+
+    fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let stored: HashMap<String, AbiEvent> = self
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(address, event)| Some((address.to_fixed_hex_string(), event.clone()?)))
+            .collect();
+
+        let result = serde_json::to_string_pretty(&stored)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("⚠️ Failed to write ABI cache {}: {}", path, e);
+        }
+    }
+}
+
+/// The layout to decode `contract`'s EventTimeout with: derived from its
+/// class ABI when `ABI_DECODING` is set and the ABI could be used, the
+/// configured manual layout otherwise.
+pub async fn layout(ctx: &Context, contract: Felt) -> EventLayout {
+    let Some(cache) = &ctx.abi_cache else {
+        return ctx.config.event_layout.clone();
+    };
+
+    let cached = cache.events.lock().unwrap().get(&contract).cloned();
+    let event = match cached {
+        Some(event) => event,
+        None => {
+            let address = contract.to_fixed_hex_string();
+            let fetched = match fetch(ctx, contract).await {
+                Ok(Some(event)) => {
+                    info!(
+                        "📜 Fetched EventTimeout ABI for {} from class {}",
+                        address, event.class_hash
+                    );
+                    Some(event)
+                }
+                Ok(None) => {
+                    warn!(
+                        "⚠️ No EventTimeout in the ABI of {}, using the manual parser",
+                        address
+                    );
+                    None
+                }
+                Err(e) => {
+                    // Not cached, so the next event from it tries again.
+                    warn!(
+                        "⚠️ Failed to fetch the ABI of {}, using the manual parser for now: {}",
+                        address, e
+                    );
+                    return ctx.config.event_layout.clone();
+                }
+            };
+            let found = fetched.is_some();
+            cache
+                .events
+                .lock()
+                .unwrap()
+                .insert(contract, fetched.clone());
+            if found {
+                cache.persist();
+            }
+            fetched
+        }
+    };
+
+    event
+        .and_then(|event| {
+            let layout = event_layout(
+                &event.members,
+                &ctx.config.abi_outcome_member,
+                &ctx.config.abi_timestamp_member,
+            );
+            if layout.is_none() {
+                warn!(
+                    "⚠️ Can't place {} and {} in the EventTimeout ABI of {}, using the manual parser",
+                    ctx.config.abi_outcome_member,
+                    ctx.config.abi_timestamp_member,
+                    contract.to_fixed_hex_string()
+                );
+            }
+            layout
+        })
+        .unwrap_or_else(|| ctx.config.event_layout.clone())
+}
+
+async fn fetch(ctx: &Context, contract: Felt) -> Result<Option<AbiEvent>, String> {
+    let block = BlockId::Tag(BlockTag::Latest);
+    let class_hash = ctx
+        .provider
+        .get_class_hash_at(block, contract)
+        .await
+        .map_err(|e| e.to_string())?;
+    let class = ctx
+        .provider
+        .get_class(block, class_hash)
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(members) = find_event(&class) {
+        return Ok(Some(AbiEvent {
+            class_hash: class_hash.to_fixed_hex_string(),
+            members,
+        }));
+    }
+
+    // No EventTimeout of its own: it may be a proxy, so look for it in the
+    // implementation's class instead.
+    for function in PROXY_FUNCTIONS {
+        let call = FunctionCall {
+            contract_address: contract,
+            entry_point_selector: get_selector_from_name(function)
+                .expect("Invalid proxy function name"),
+            calldata: vec![],
+        };
+        let Ok(result) = ctx.provider.call(call, block).await else {
+            continue;
+        };
+        let Some(implementation) = result.first().copied() else {
+            continue;
+        };
+
+        let class = match ctx.provider.get_class(block, implementation).await {
+            Ok(class) => class,
+            Err(_) => match ctx.provider.get_class_at(block, implementation).await {
+                Ok(class) => class,
+                Err(_) => continue,
+            },
+        };
+        if let Some(members) = find_event(&class) {
+            return Ok(Some(AbiEvent {
+                class_hash: implementation.to_fixed_hex_string(),
+                members,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Finds EventTimeout's data members in a Sierra (both event ABI
+/// versions) or legacy class.
+fn find_event(class: &ContractClass) -> Option<Vec<Member>> {
+    let abi: Vec<Value> = match class {
+        ContractClass::Sierra(class) => serde_json::from_str(&class.abi).ok()?,
+        ContractClass::Legacy(class) => {
+            serde_json::from_value(serde_json::to_value(class.abi.as_ref()?).ok()?).ok()?
+        }
+    };
+
+    abi.iter().find_map(|entry| {
+        if entry.get("type")?.as_str()? != "event" {
+            return None;
+        }
+        let name = entry.get("name")?.as_str()?;
+        if name.rsplit("::").next() != Some("EventTimeout")
+            || entry.get("kind").and_then(Value::as_str) == Some("enum")
+        {
+            return None;
+        }
+
+        let members = entry
+            .get("members")
+            .or_else(|| entry.get("data"))
+            .or_else(|| entry.get("inputs"))?
+            .as_array()?;
+        Some(
+            members
+                .iter()
+                // Newer ABIs mark which members are keys; those aren't in
+                // `data`.
+                .filter(|member| {
+                    member
+                        .get("kind")
+                        .and_then(Value::as_str)
+                        .map_or(true, |kind| kind == "data")
+                })
+                .filter_map(|member| {
+                    Some(Member {
+                        name: member.get("name")?.as_str()?.to_string(),
+                        ty: member.get("type")?.as_str()?.to_string(),
+                    })
+                })
+                .collect(),
+        )
+    })
+}
+
+/// Places the outcome and timestamp members by adding up the serialized
+/// size of the members before them. Gives up on any type whose size isn't
+/// known, since every later offset would be wrong.
+fn event_layout(members: &[Member], outcome: &str, timestamp: &str) -> Option<EventLayout> {
+    let (mut outcome_decoder, mut timestamp_decoder) = (None, None);
+    let mut index = 0;
+    for member in members {
+        if outcome_decoder.is_some() && timestamp_decoder.is_some() {
+            break;
+        }
+        let size = felt_size(&member.ty)?;
+        let decoder = match size {
+            1 => FieldDecoder::Direct { index },
+            count => FieldDecoder::Limbs {
+                index,
+                count,
+                little_endian: true,
+            },
+        };
+        if member.name == outcome {
+            outcome_decoder = Some(decoder);
+        } else if member.name == timestamp {
+            timestamp_decoder = Some(decoder);
+        }
+        index += size;
+    }
+
+    Some(EventLayout {
+        outcome: outcome_decoder?,
+        timestamp: timestamp_decoder?,
+    })
+}
+
+fn felt_size(ty: &str) -> Option<usize> {
+    let name = ty.rsplit("::").next()?;
+    match name {
+        "u256" | "Uint256" => Some(2),
+        "felt" | "felt252" | "bool" | "u8" | "u16" | "u32" | "u64" | "u128" | "i8" | "i16"
+        | "i32" | "i64" | "i128" | "ContractAddress" | "ClassHash" | "EthAddress" => Some(1),
+        _ => None,
+    }
+}
@@ -0,0 +1,92 @@
+use log::{error, warn};
+use serde_json::{json, Value};
+use starknet::core::types::EmittedEvent;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Posts operator alerts to `ALERT_WEBHOOK_URL`. Alerts are sent in the
+/// background and rate-limited to one per `min_interval`; the ones dropped
+/// in between are counted in the next alert.
+pub struct Alerter {
+    url: String,
+    min_interval: Duration,
+    client: reqwest::Client,
+    state: Mutex<RateLimit>,
+}
+
+#[derive(Default)]
+struct RateLimit {
+    last_sent: Option<Instant>,
+    suppressed: u64,
+}
+
+impl Alerter {
+    pub fn new(url: String, min_interval: Duration) -> Self {
+        Alerter {
+            url,
+            min_interval,
+            client: reqwest::Client::new(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Alerts with an event's raw payload, so the parse can be reproduced
+    /// against the exact bytes.
+    pub fn deadletter(&self, event: &EmittedEvent, reason: &str) {
+        self.send(json!({
+            "type": "deadletter",
+            "reason": reason,
+            "contract_address": event.from_address.to_fixed_hex_string(),
+            "block_number": event.block_number,
+            "transaction_hash": event.transaction_hash.to_fixed_hex_string(),
+            "keys": event.keys.iter().map(|key| key.to_hex_string()).collect::<Vec<_>>(),
+            "data": event.data.iter().map(|value| value.to_hex_string()).collect::<Vec<_>>(),
+        }));
+    }
+
+    /// Alerts with a free-form operational message.
+    pub fn notice(&self, kind: &str, message: &str) {
+        self.send(json!({
+            "type": kind,
+            "message": message,
+        }));
+    }
+
+    /// Alerts with a change of state, such as falling behind or catching up.
+    /// These fire once per transition, so they skip the rate limit: a
+    /// recovery dropped for following its own incident too closely would
+    /// leave the incident looking open.
+    pub fn transition(&self, kind: &str, message: &str, block_number: u64) {
+        let payload = json!({
+            "type": kind,
+            "message": message,
+            "block_number": block_number,
+        });
+        self.post(payload);
+    }
+
+    fn send(&self, mut payload: Value) {
+        let suppressed = {
+            let mut state = self.state.lock().unwrap();
+            if matches!(state.last_sent, Some(at) if at.elapsed() < self.min_interval) {
+                state.suppressed += 1;
+                warn!("🔕 Alert rate-limited ({} suppressed)", state.suppressed);
+                return;
+            }
+            state.last_sent = Some(Instant::now());
+            std::mem::take(&mut state.suppressed)
+        };
+        payload["suppressed_since_last"] = json!(suppressed);
+        self.post(payload);
+    }
+
+    fn post(&self, payload: Value) {
+        let request = self.client.post(&self.url).json(&payload);
+        tokio::spawn(async move {
+            let result = request.send().await.and_then(|r| r.error_for_status());
+            if let Err(e) = result {
+                error!("Failed to send alert webhook: {}", e);
+            }
+        });
+    }
+}
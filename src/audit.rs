@@ -0,0 +1,219 @@
+use crate::{
+    abi, after_commit, event_timeout_event_key, fetch_contract_addresses, fetch_events_page,
+    get_last_processed_block, quarantine, resolution_from, set_resolved_by,
+    update_database_for_event_finished, Context, EventTimeout, Written,
+};
+use log::{error, info, warn};
+use sqlx::Row;
+use starknet::core::types::{BlockId, EventFilter};
+use std::collections::HashMap;
+
+/// Most blocks one audit pass covers, so a long backlog doesn't hold up
+/// the main loop.
+const AUDIT_MAX_BLOCKS: u64 = 1000;
+
+/// Slower second pass over blocks `AUDIT_LAG_BLOCKS` behind the main
+/// cursor. Re-reads every EventTimeout from a tracked contract once it is
+/// deeply final, runs it through the main pass's checks, checks that
+/// the matching `events` row is resolved with that outcome, and reports
+/// (and with `AUDIT_RECONCILE`, re-applies) any that aren't. Its progress
+/// is kept in `block_state_trunks` row 2.
+pub async fn run(ctx: &Context, lag: u64) {
+    let main_cursor = get_last_processed_block(&ctx.control_pool).await;
+    let Some(target) = main_cursor.checked_sub(lag) else {
+        return;
+    };
+
+    // A first run starts auditing from here rather than from genesis.
+    let result = sqlx::query(
+        "INSERT INTO block_state_trunks (id, last_processed_block) VALUES (2, $1)
+         ON CONFLICT (id) DO NOTHING",
+    )
+    .bind(target as i64)
+    .execute(&ctx.control_pool)
+    .await;
+    if let Err(e) = result {
+        error!("Failed to initialize the audit cursor: {}", e);
+        return;
+    }
+
+    let audit_cursor: i64 = match sqlx::query_scalar(
+        "SELECT last_processed_block FROM block_state_trunks WHERE id = 2",
+    )
+    .fetch_one(&ctx.control_pool)
+    .await
+    {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            error!("Failed to read the audit cursor: {}", e);
+            return;
+        }
+    };
+    let from = audit_cursor as u64 + 1;
+    if from > target {
+        return;
+    }
+    let mut to = target.min(from + AUDIT_MAX_BLOCKS - 1);
+    // A quarantined contract is only caught up to its next block, so the
+    // audit waits for it there.
+    if ctx.quarantine.is_some() {
+        match quarantine::held(&ctx.control_pool).await {
+            Ok(held) => {
+                for (_, next_block) in held {
+                    to = to.min(next_block.saturating_sub(1));
+                }
+            }
+            Err(e) => {
+                error!("Failed to load quarantined contracts for the audit: {}", e);
+                return;
+            }
+        }
+        if to < from {
+            return;
+        }
+    }
+
+    // The last resolution per address wins, as in the main pass.
+    let mut resolutions: HashMap<String, EventTimeout> = HashMap::new();
+    for contract in fetch_contract_addresses(&ctx.control_pool, &ctx.config, true).await {
+        let filter = EventFilter {
+            from_block: Some(BlockId::Number(from)),
+            to_block: Some(BlockId::Number(to)),
+            address: Some(contract.address),
+            keys: Some(vec![vec![event_timeout_event_key()]]),
+        };
+        let layout = abi::layout(ctx, contract.address).await;
+        let mut continuation_token = None;
+        loop {
+            let page = match fetch_events_page(ctx, &filter, continuation_token).await {
+                Ok(page) => page,
+                Err(e) => {
+                    error!("Audit of blocks {}..={} failed: {}", from, to, e);
+                    return;
+                }
+            };
+            for event in &page.events {
+                // Refused events were recorded by the main pass already.
+                if let Ok(resolution) = resolution_from(ctx, &contract, &layout, event).await {
+                    resolutions.insert(resolution.event_address.clone(), resolution);
+                }
+            }
+            continuation_token = page.continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+    }
+    let audited = resolutions.len();
+
+    let mut discrepancies = 0;
+    for mut resolution in resolutions.into_values() {
+        match verify(ctx, &resolution).await {
+            Ok(None) => {}
+            Ok(Some(problem)) => {
+                discrepancies += 1;
+                let message = format!(
+                    "Audit: {} from block {:?} {}",
+                    resolution.event_address, resolution.block_number, problem
+                );
+                error!("🔎 {}", message);
+                if let Some(alerter) = &ctx.alerter {
+                    alerter.notice("audit_discrepancy", &message);
+                }
+                if ctx.config.audit_reconcile {
+                    set_resolved_by(ctx, &mut resolution).await;
+                    reconcile(ctx, &resolution).await;
+                }
+            }
+            Err(e) => {
+                error!(
+                    "Failed to audit {}, stopping before block {}: {}",
+                    resolution.event_address, from, e
+                );
+                return;
+            }
+        }
+    }
+
+    if let Err(e) =
+        sqlx::query("UPDATE block_state_trunks SET last_processed_block = $1 WHERE id = 2")
+            .bind(to as i64)
+            .execute(&ctx.control_pool)
+            .await
+    {
+        error!("Failed to advance the audit cursor: {}", e);
+        return;
+    }
+
+    if discrepancies > 0 {
+        warn!(
+            "⚠️ Audit of blocks {}..={} found {} discrepancy(ies) in {} resolution(s)",
+            from, to, discrepancies, audited
+        );
+    } else {
+        info!(
+            "🔎 Audit of blocks {}..={} verified {} resolution(s)",
+            from, to, audited
+        );
+    }
+}
+
+/// Returns what's wrong with the stored state for `resolution`, or `None`
+/// if it matches or the address isn't a market this database tracks.
+async fn verify(ctx: &Context, resolution: &EventTimeout) -> Result<Option<String>, sqlx::Error> {
+    let config = &ctx.config;
+    let query = format!(
+        "SELECT ({} = $2::{}) AS outcome_matches, ({}) AS resolved
+         FROM events WHERE address = $1",
+        config.outcome_column,
+        config.outcome_column_type,
+        config.resolution_model.resolved_filter()
+    );
+    let Some(row) = sqlx::query(&query)
+        .bind(&resolution.event_address)
+        .bind(resolution.event_outcome as i32)
+        .fetch_optional(&ctx.pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let resolved: Option<bool> = row.get("resolved");
+    let outcome_matches: Option<bool> = row.get("outcome_matches");
+    Ok(match (resolved, outcome_matches) {
+        (Some(true), Some(true)) => None,
+        (Some(true), _) => Some(format!(
+            "is resolved with a different outcome than {}",
+            resolution.event_outcome
+        )),
+        _ => Some(format!(
+            "is not resolved (outcome {})",
+            resolution.event_outcome
+        )),
+    })
+}
+
+async fn reconcile(ctx: &Context, resolution: &EventTimeout) {
+    let result = async {
+        let mut tx = ctx.pool.begin().await?;
+        let written = update_database_for_event_finished(resolution, &mut tx, &ctx.config).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(written)
+    }
+    .await;
+
+    match result {
+        Ok(Written::Applied) => {
+            after_commit(ctx, std::slice::from_ref(resolution));
+            info!("🔧 Audit re-applied {}", resolution.event_address);
+        }
+        Ok(Written::Buffered) => info!(
+            "🔧 Audit buffered {} until its events row exists",
+            resolution.event_address
+        ),
+        Err(e) => error!(
+            "Failed to re-apply {} during audit: {}",
+            resolution.event_address, e
+        ),
+    }
+}
@@ -0,0 +1,198 @@
+use log::{error, info};
+use sqlx::{Pool, Postgres};
+use starknet::core::types::{BlockId, BlockStatus, Felt, MaybePendingBlockWithTxHashes};
+use starknet::providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider, ProviderError};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Headers kept in memory; older block numbers are evicted first.
+const HEADER_CACHE_SIZE: usize = 256;
+
+/// Blocks rechecked for L1 acceptance per pass.
+const L1_BACKFILL_BATCH: i64 = 100;
+
+#[derive(Debug, Clone)]
+pub struct BlockHeader {
+    pub block_number: u64,
+    pub block_hash: Felt,
+    pub parent_hash: Felt,
+    pub timestamp: u64,
+    /// Transaction hashes in execution order.
+    pub transactions: Vec<Felt>,
+    /// Whether the block was accepted on L1 when the header was fetched.
+    pub accepted_on_l1: bool,
+}
+
+/// Fetches each block header at most once, however many contracts or
+/// events in that block need it.
+#[derive(Default)]
+pub struct HeaderCache {
+    headers: Mutex<BTreeMap<u64, BlockHeader>>,
+}
+
+impl HeaderCache {
+    pub async fn get(
+        &self,
+        provider: &JsonRpcClient<HttpTransport>,
+        block_number: u64,
+    ) -> Result<Option<BlockHeader>, ProviderError> {
+        let cached = self.headers.lock().unwrap().get(&block_number).cloned();
+        if cached.is_some() {
+            return Ok(cached);
+        }
+
+        let Some(header) = fetch_header(provider, block_number).await? else {
+            return Ok(None);
+        };
+
+        let mut headers = self.headers.lock().unwrap();
+        headers.insert(block_number, header.clone());
+        while headers.len() > HEADER_CACHE_SIZE {
+            headers.pop_first();
+        }
+
+        Ok(Some(header))
+    }
+
+    /// Cached headers up to `block_number`, for a restart snapshot.
+    pub fn entries(&self, up_to: u64) -> Vec<BlockHeader> {
+        let headers = self.headers.lock().unwrap();
+        headers
+            .range(..=up_to)
+            .map(|(_, header)| header.clone())
+            .collect()
+    }
+
+    /// Seeds the cache from a restart snapshot.
+    pub fn restore(&self, restored: Vec<BlockHeader>) {
+        let mut headers = self.headers.lock().unwrap();
+        headers.extend(
+            restored
+                .into_iter()
+                .map(|header| (header.block_number, header)),
+        );
+        while headers.len() > HEADER_CACHE_SIZE {
+            headers.pop_first();
+        }
+    }
+
+    /// Drops cached headers from `block_number` up, after a reorg.
+    pub fn invalidate_from(&self, block_number: u64) {
+        self.headers.lock().unwrap().split_off(&block_number);
+    }
+}
+
+/// Fetches a header straight from the RPC, bypassing the cache. Returns
+/// `None` if the block is still pending.
+pub async fn fetch_header(
+    provider: &JsonRpcClient<HttpTransport>,
+    block_number: u64,
+) -> Result<Option<BlockHeader>, ProviderError> {
+    match provider
+        .get_block_with_tx_hashes(BlockId::Number(block_number))
+        .await?
+    {
+        MaybePendingBlockWithTxHashes::Block(block) => Ok(Some(BlockHeader {
+            block_number: block.block_number,
+            block_hash: block.block_hash,
+            parent_hash: block.parent_hash,
+            timestamp: block.timestamp,
+            transactions: block.transactions,
+            accepted_on_l1: block.status == BlockStatus::AcceptedOnL1,
+        })),
+        MaybePendingBlockWithTxHashes::PendingBlock(_) => Ok(None),
+    }
+}
+
+/// Records a processed block's metadata for time-based queries.
+pub async fn upsert_block(pool: &Pool<Postgres>, header: &BlockHeader, event_count: usize) {
+    let result = sqlx::query(
+        "INSERT INTO blocks
+            (block_number, block_hash, block_timestamp, event_count,
+             accepted_on_l1, accepted_on_l1_at)
+         VALUES ($1, $2, $3, $4, $5, CASE WHEN $5 THEN now() END)
+         ON CONFLICT (block_number) DO UPDATE
+         SET block_hash = EXCLUDED.block_hash,
+             block_timestamp = EXCLUDED.block_timestamp,
+             event_count = EXCLUDED.event_count,
+             accepted_on_l1 = EXCLUDED.accepted_on_l1,
+             accepted_on_l1_at = CASE WHEN EXCLUDED.accepted_on_l1
+                 THEN COALESCE(blocks.accepted_on_l1_at, now()) END,
+             indexed_at = now()",
+    )
+    .bind(header.block_number as i64)
+    .bind(header.block_hash.to_fixed_hex_string())
+    .bind(header.timestamp as i64)
+    .bind(event_count as i32)
+    .bind(header.accepted_on_l1)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(_) => info!(
+            "🧱 Recorded block {} ({} events)",
+            header.block_number, event_count
+        ),
+        Err(e) => error!(
+            "Failed to record metadata for block {}: {}",
+            header.block_number, e
+        ),
+    }
+}
+
+/// Rechecks the oldest blocks not yet accepted on L1 and marks those that
+/// are now. Blocks reach L1 in order, so it stops at the first one that
+/// still hasn't.
+pub async fn backfill_l1_acceptance(
+    provider: &JsonRpcClient<HttpTransport>,
+    pool: &Pool<Postgres>,
+) {
+    let pending: Vec<i64> = match sqlx::query_scalar(
+        "SELECT block_number FROM blocks
+         WHERE NOT accepted_on_l1
+         ORDER BY block_number
+         LIMIT $1",
+    )
+    .bind(L1_BACKFILL_BATCH)
+    .fetch_all(pool)
+    .await
+    {
+        Ok(pending) => pending,
+        Err(e) => {
+            error!("Failed to load blocks awaiting L1 acceptance: {}", e);
+            return;
+        }
+    };
+
+    let mut accepted = 0;
+    for block_number in pending {
+        match fetch_header(provider, block_number as u64).await {
+            Ok(Some(header)) if header.accepted_on_l1 => {}
+            Ok(_) => break,
+            Err(e) => {
+                error!("Failed to fetch header for block {}: {}", block_number, e);
+                break;
+            }
+        }
+
+        let result = sqlx::query(
+            "UPDATE blocks SET accepted_on_l1 = TRUE, accepted_on_l1_at = now()
+             WHERE block_number = $1",
+        )
+        .bind(block_number)
+        .execute(pool)
+        .await;
+        if let Err(e) = result {
+            error!(
+                "Failed to mark block {} accepted on L1: {}",
+                block_number, e
+            );
+            break;
+        }
+        accepted += 1;
+    }
+
+    if accepted > 0 {
+        info!("⛓️ Marked {} block(s) accepted on L1", accepted);
+    }
+}
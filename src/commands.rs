@@ -0,0 +1,953 @@
+use crate::abi;
+use crate::config::ResolutionModel;
+use crate::digest_log::DigestSink;
+use crate::{
+    after_commit, event_filter, event_timeout_event_key, fetch_contract_addresses, format_address,
+    indexed_events, parse_event_finished_event, process_block_range, set_resolved_by,
+    update_database_for_event_finished, Context, Written, EVENTS_CHUNK_SIZE, MIGRATOR,
+};
+use log::error;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use starknet::core::types::{BlockId, EmittedEvent, EventFilter, Felt};
+use starknet::providers::{Provider, ProviderError};
+use std::fs;
+use std::time::Instant;
+
+/// One-shot subcommands run instead of the indexing loop.
+#[derive(Debug)]
+pub enum Command {
+    /// Print the event selectors and per-contract filters, then exit.
+    Describe,
+    /// Apply pending database migrations, then exit.
+    Migrate,
+    /// Print the raw events the RPC returns for a block, then exit.
+    DumpBlock {
+        block_number: u64,
+        contract: Option<Felt>,
+    },
+    /// Fetch the last `blocks` blocks the way the indexer does and report
+    /// RPC throughput, without writing anything.
+    BenchRpc { blocks: u64 },
+    /// Rewrite `events.address` and `bets.event_address` in canonical
+    /// zero-padded form.
+    CanonicalizeAddresses { dry_run: bool },
+    /// Process exactly the `from-to` ranges listed in a file, skipping the
+    /// ones already recorded in `processed_ranges`. `include_inactive`
+    /// covers contracts that are no longer active too.
+    ReindexRanges {
+        file: String,
+        include_inactive: bool,
+    },
+    /// Mark winning bets of already-resolved events claimable where an
+    /// earlier resolution missed them.
+    RepairClaimables,
+    /// Testing aid: corrupt the newest stored block hashes so the next pass
+    /// takes the reorg rollback path.
+    SimulateReorg { depth: u64 },
+    /// Print the tables and columns this configuration reads and writes,
+    /// and the routing mappings.
+    DescribeSchema { json: bool },
+    /// Re-derive the resolution of each event address listed in a file from
+    /// its on-chain EventTimeout.
+    ReindexEvents { file: String },
+}
+
+impl Command {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<Command> {
+        let name = args.next()?;
+
+        match name.as_str() {
+            "describe" => Some(Command::Describe),
+            "describe-schema" => {
+                let json = match args.next().as_deref() {
+                    Some("--json") => true,
+                    Some(other) => panic!("Unexpected argument '{}'", other),
+                    None => false,
+                };
+
+                Some(Command::DescribeSchema { json })
+            }
+            "migrate" => Some(Command::Migrate),
+            "dump-block" => {
+                let block_number = args
+                    .next()
+                    .expect("Usage: dump-block <block_number> [--contract <addr>]")
+                    .parse()
+                    .expect("Invalid block number");
+                let contract = match args.next().as_deref() {
+                    Some("--contract") => Some(
+                        Felt::from_hex(&args.next().expect("--contract requires an address"))
+                            .expect("Invalid contract address"),
+                    ),
+                    Some(other) => panic!("Unexpected argument '{}'", other),
+                    None => None,
+                };
+
+                Some(Command::DumpBlock {
+                    block_number,
+                    contract,
+                })
+            }
+            "bench-rpc" => {
+                let blocks = match args.next().as_deref() {
+                    Some("--blocks") => args
+                        .next()
+                        .expect("--blocks requires a number")
+                        .parse()
+                        .expect("Invalid block count"),
+                    Some(other) => panic!("Unexpected argument '{}'", other),
+                    None => 100,
+                };
+
+                Some(Command::BenchRpc { blocks })
+            }
+            "canonicalize-addresses" => {
+                let dry_run = match args.next().as_deref() {
+                    Some("--dry-run") => true,
+                    Some(other) => panic!("Unexpected argument '{}'", other),
+                    None => false,
+                };
+
+                Some(Command::CanonicalizeAddresses { dry_run })
+            }
+            "repair-claimables" => Some(Command::RepairClaimables),
+            "simulate-reorg" => {
+                let depth = match args.next().as_deref() {
+                    Some("--depth") => args
+                        .next()
+                        .expect("--depth requires a number")
+                        .parse()
+                        .expect("Invalid depth"),
+                    Some(other) => panic!("Unexpected argument '{}'", other),
+                    None => 1,
+                };
+
+                Some(Command::SimulateReorg { depth })
+            }
+            "reindex-events" => {
+                let file = match args.next().as_deref() {
+                    Some("--addresses") => args.next().expect("--addresses requires a path"),
+                    _ => panic!("Usage: reindex-events --addresses <path>"),
+                };
+
+                Some(Command::ReindexEvents { file })
+            }
+            "reindex-ranges" => {
+                let (mut file, mut include_inactive) = (None, false);
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--file" => file = Some(args.next().expect("--file requires a path")),
+                        "--include-inactive" => include_inactive = true,
+                        other => panic!("Unexpected argument '{}'", other),
+                    }
+                }
+                let file = file.expect("Usage: reindex-ranges --file <path> [--include-inactive]");
+
+                Some(Command::ReindexRanges {
+                    file,
+                    include_inactive,
+                })
+            }
+            other => panic!("Unknown subcommand '{}'", other),
+        }
+    }
+}
+
+pub async fn run(command: Command, ctx: &Context) {
+    match command {
+        Command::Describe => describe(ctx).await,
+        Command::Migrate => migrate(&ctx.pool).await,
+        Command::DumpBlock {
+            block_number,
+            contract,
+        } => dump_block(ctx, block_number, contract).await,
+        Command::BenchRpc { blocks } => bench_rpc(ctx, blocks).await,
+        Command::CanonicalizeAddresses { dry_run } => {
+            canonicalize_addresses(&ctx.pool, dry_run).await
+        }
+        Command::ReindexRanges {
+            file,
+            include_inactive,
+        } => reindex_ranges(ctx, &file, include_inactive).await,
+        Command::RepairClaimables => repair_claimables(ctx).await,
+        Command::SimulateReorg { depth } => simulate_reorg(ctx, depth).await,
+        Command::DescribeSchema { json } => describe_schema(ctx, json),
+        Command::ReindexEvents { file } => reindex_events(ctx, &file).await,
+    }
+}
+
+/// Runs without waiting for the companion service's tables, so schema
+/// changes can ship as their own deploy step.
+pub async fn migrate(pool: &Pool<Postgres>) {
+    let applied_before: Vec<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+            .unwrap_or_default();
+
+    if let Err(e) = MIGRATOR.run(pool).await {
+        error!("❌ Migration failed: {}", e);
+        std::process::exit(1);
+    }
+
+    let newly_applied: Vec<_> = MIGRATOR
+        .iter()
+        .filter(|migration| !applied_before.contains(&migration.version))
+        .collect();
+
+    if newly_applied.is_empty() {
+        println!("No pending migrations.");
+    }
+    for migration in newly_applied {
+        println!("Applied {} {}", migration.version, migration.description);
+    }
+}
+
+async fn describe(ctx: &Context) {
+    println!("Indexed events:");
+    for (name, selector) in indexed_events(ctx) {
+        println!("  {} => {}", name, selector.to_fixed_hex_string());
+    }
+
+    let contract_addresses = fetch_contract_addresses(&ctx.control_pool, &ctx.config, false).await;
+    println!("Filters ({} active contracts):", contract_addresses.len());
+    for contract in contract_addresses {
+        let filter = event_filter(ctx, contract.address, 0, 0);
+        println!(
+            "  {} => keys {:?}",
+            contract.display_name(),
+            filter.keys.unwrap_or_default()
+        );
+    }
+}
+
+/// Dumps every event emitted in `block_number` (optionally restricted to one
+/// contract) exactly as the RPC returns it, with no key filter or parsing.
+async fn dump_block(ctx: &Context, block_number: u64, contract: Option<Felt>) {
+    let filter = EventFilter {
+        from_block: Some(BlockId::Number(block_number)),
+        to_block: Some(BlockId::Number(block_number)),
+        address: contract,
+        keys: None,
+    };
+
+    let mut events: Vec<EmittedEvent> = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let page = ctx
+            .provider
+            .get_events(filter.clone(), continuation_token, 100)
+            .await
+            .expect("Failed to fetch events");
+        events.extend(page.events);
+
+        continuation_token = page.continuation_token;
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&events).expect("Failed to serialize events")
+    );
+}
+
+/// Mirrors `process_block`'s fetch pattern (one block and contract per
+/// filter, paginated by `EVENTS_CHUNK_SIZE`) so the numbers reflect what a
+/// catch-up pass would cost.
+async fn bench_rpc(ctx: &Context, blocks: u64) {
+    let contract_addresses = fetch_contract_addresses(&ctx.control_pool, &ctx.config, false).await;
+    let started = Instant::now();
+
+    let latest_block = ctx
+        .provider
+        .block_number()
+        .await
+        .expect("Failed to fetch latest block number");
+    let first_block = latest_block.saturating_sub(blocks.saturating_sub(1));
+
+    let mut calls: u64 = 1;
+    let mut events: u64 = 0;
+    for block_number in first_block..=latest_block {
+        for contract in &contract_addresses {
+            let filter = event_filter(ctx, contract.address, block_number, block_number);
+            let mut continuation_token = None;
+            loop {
+                let page = ctx
+                    .provider
+                    .get_events(filter.clone(), continuation_token, EVENTS_CHUNK_SIZE)
+                    .await
+                    .expect("Failed to fetch events");
+                calls += 1;
+                events += page.events.len() as u64;
+
+                continuation_token = page.continuation_token;
+                if continuation_token.is_none() {
+                    break;
+                }
+            }
+        }
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    let block_count = latest_block - first_block + 1;
+    println!(
+        "Blocks {}..={} ({} blocks, {} contracts)",
+        first_block,
+        latest_block,
+        block_count,
+        contract_addresses.len()
+    );
+    println!("RPC calls:      {}", calls);
+    println!("Events fetched: {}", events);
+    println!("Total time:     {:.2}s", elapsed);
+    println!("Blocks/sec:     {:.2}", block_count as f64 / elapsed);
+    println!("Calls/sec:      {:.2}", calls as f64 / elapsed);
+}
+
+/// Runs every update in one transaction, which a dry run rolls back, so the
+/// reported counts are exact either way.
+async fn canonicalize_addresses(pool: &Pool<Postgres>, dry_run: bool) {
+    let mut tx = pool.begin().await.expect("Failed to start transaction");
+
+    let mut changed = 0;
+    for (table, column) in [("events", "address"), ("bets", "event_address")] {
+        let addresses: Vec<String> =
+            sqlx::query_scalar(&format!("SELECT DISTINCT {} FROM {}", column, table))
+                .fetch_all(&mut tx)
+                .await
+                .expect("Failed to read addresses");
+
+        let mut table_changed = 0;
+        for address in addresses {
+            let canonical = match Felt::from_hex(&address) {
+                Ok(felt) => felt.to_fixed_hex_string(),
+                Err(_) => {
+                    println!("Skipping invalid {}.{} '{}'", table, column, address);
+                    continue;
+                }
+            };
+            if canonical == address {
+                continue;
+            }
+
+            let result = sqlx::query(&format!(
+                "UPDATE {} SET {} = $1 WHERE {} = $2",
+                table, column, column
+            ))
+            .bind(&canonical)
+            .bind(&address)
+            .execute(&mut tx)
+            .await;
+
+            match result {
+                Ok(result) => {
+                    println!("{}.{}: {} -> {}", table, column, address, canonical);
+                    table_changed += result.rows_affected();
+                }
+                Err(e) => {
+                    error!(
+                        "❌ Failed to canonicalize {}.{} '{}', nothing was changed: {}",
+                        table, column, address, e
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        println!("{} row(s) changed in {}", table_changed, table);
+        changed += table_changed;
+    }
+
+    if dry_run {
+        tx.rollback().await.expect("Failed to roll back");
+        println!("Dry run: {} row(s) would change, nothing written.", changed);
+    } else {
+        tx.commit().await.expect("Failed to commit");
+        println!("{} row(s) changed.", changed);
+    }
+}
+
+/// One `from-to` range per line; blank lines and `#` comments are ignored.
+fn read_ranges(path: &str) -> Vec<(u64, u64)> {
+    let contents =
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (from, to) = line
+                .split_once('-')
+                .unwrap_or_else(|| panic!("Invalid range '{}', expected from-to", line));
+            let from: u64 = from.trim().parse().expect("Invalid range start");
+            let to: u64 = to.trim().parse().expect("Invalid range end");
+            if from == 0 || from > to {
+                panic!("Invalid range '{}'", line);
+            }
+            (from, to)
+        })
+        .collect()
+}
+
+/// Leaves the cursor alone: the ranges are repairs behind it, and writes
+/// are idempotent so overlapping the live indexer is harmless.
+async fn reindex_ranges(ctx: &Context, path: &str, include_inactive: bool) {
+    let ranges = read_ranges(path);
+    let contract_addresses =
+        fetch_contract_addresses(&ctx.control_pool, &ctx.config, include_inactive).await;
+
+    let mut failed = 0;
+    for (from, to) in ranges {
+        let done: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM processed_ranges WHERE from_block = $1 AND to_block = $2)",
+        )
+        .bind(from as i64)
+        .bind(to as i64)
+        .fetch_one(&ctx.control_pool)
+        .await
+        .expect("Failed to read processed_ranges");
+        if done {
+            println!("Skipping {}-{}, already processed", from, to);
+            continue;
+        }
+
+        let committed = process_block_range(ctx, &contract_addresses, from, to, false).await;
+        if committed < to {
+            error!(
+                "❌ Range {}-{} stopped after block {}, rerun to retry it",
+                from, to, committed
+            );
+            failed += 1;
+            continue;
+        }
+
+        sqlx::query(
+            "INSERT INTO processed_ranges (from_block, to_block) VALUES ($1, $2)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(from as i64)
+        .bind(to as i64)
+        .execute(&ctx.control_pool)
+        .await
+        .expect("Failed to record processed range");
+        println!("Processed {}-{}", from, to);
+    }
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Re-runs the bets half of `update_database_for_event_finished` for every
+/// resolved event in one statement. Only bets not yet claimable are touched,
+/// so it is safe to run repeatedly.
+async fn repair_claimables(ctx: &Context) {
+    let config = &ctx.config;
+    if config.resolve_function.is_some() {
+        println!(
+            "Note: RESOLVE_FUNCTION is set; this applies Trunks' inline bets logic, not the function's."
+        );
+    }
+
+    let cancelled = match config.cancel_outcome {
+        Some(outcome) => format!("{}::TEXT = '{}'", config.outcome_column, outcome),
+        None => "FALSE".to_string(),
+    };
+    let (market_select, market_join) = match config.market_identity.market_id() {
+        Some(market_id) => (
+            format!(", {}::TEXT AS market_id", market_id.events_column),
+            format!(
+                " AND bets.{}::TEXT = resolved.market_id",
+                market_id.bets_column
+            ),
+        ),
+        None => (String::new(), String::new()),
+    };
+    let result = sqlx::query(&format!(
+        "UPDATE bets SET is_claimable = TRUE
+         FROM (
+            SELECT address{}, {} AS cancelled,
+                   CASE WHEN {} THEN NULL ELSE {} END AS winning_bet
+            FROM events WHERE {}
+         ) resolved
+         WHERE bets.event_address = resolved.address{}
+           AND ({} OR bets.bet = resolved.winning_bet)
+           AND bets.is_claimable IS NOT TRUE",
+        market_select,
+        cancelled,
+        cancelled,
+        config.bet_mapping.winning_bet_sql(&config.outcome_column),
+        config.resolution_model.resolved_filter(),
+        market_join,
+        // Refunds that don't go through is_claimable are left alone.
+        if config.cancel_marks_claimable {
+            "resolved.cancelled"
+        } else {
+            "FALSE"
+        }
+    ))
+    .execute(&ctx.pool)
+    .await;
+
+    match result {
+        Ok(result) => println!("{} bet(s) fixed.", result.rows_affected()),
+        Err(e) => {
+            error!("❌ Failed to repair claimable bets: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Overwrites the hashes of the newest `depth` blocks in `recent_blocks`
+/// with a value no real block has. Only the stored window changes, so the
+/// next pass detects the mismatch and rolls back `depth` blocks exactly as
+/// it would for a real reorg.
+async fn simulate_reorg(ctx: &Context, depth: u64) {
+    if ctx.config.recent_blocks_depth == 0 {
+        error!("❌ Reorg detection is disabled, set RECENT_BLOCKS_DEPTH first");
+        std::process::exit(1);
+    }
+
+    let corrupted: Vec<i64> = sqlx::query_scalar(
+        "UPDATE recent_blocks SET block_hash = $1
+         WHERE block_number IN (
+            SELECT block_number FROM recent_blocks ORDER BY block_number DESC LIMIT $2
+         )
+         RETURNING block_number",
+    )
+    .bind(format!("0x{:0>64}", "dead"))
+    .bind(depth as i64)
+    .fetch_all(&ctx.control_pool)
+    .await
+    .expect("Failed to corrupt recent block hashes");
+
+    if corrupted.is_empty() {
+        println!("No recent blocks stored yet, run a pass first.");
+        return;
+    }
+    println!(
+        "Corrupted stored hash of block(s) {:?}; the next pass should roll back to block {}.",
+        corrupted,
+        corrupted.iter().min().unwrap() - 1
+    );
+}
+
+#[derive(Serialize)]
+struct TableUsage {
+    table: String,
+    reads: Vec<String>,
+    writes: Vec<String>,
+    /// When the table is touched, if not on every pass.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    when: Option<&'static str>,
+}
+
+impl TableUsage {
+    fn new(table: &str, reads: &[&str], writes: &[&str], when: Option<&'static str>) -> Self {
+        TableUsage {
+            table: table.to_string(),
+            reads: reads.iter().map(|column| column.to_string()).collect(),
+            writes: writes.iter().map(|column| column.to_string()).collect(),
+            when,
+        }
+    }
+}
+
+/// Derived from the effective config, so it reflects what this deployment
+/// actually does rather than every possible query.
+fn schema_usage(ctx: &Context) -> Vec<TableUsage> {
+    let config = &ctx.config;
+
+    let mut events_reads = vec!["address", "is_active"];
+    let mut events_writes = vec![config.outcome_column.as_str()];
+    match config.resolution_model {
+        ResolutionModel::Flip => events_writes.push("is_active"),
+        ResolutionModel::Soft => {
+            events_reads.push("resolved_at");
+            events_writes.extend(["resolved_at", "status"]);
+        }
+    }
+    events_reads.extend(config.event_label_column.as_deref());
+    events_reads.extend(config.allowed_outcomes_column.as_deref());
+    // ALLOWED_OUTCOMES_COLUMN takes precedence, as in `contracts_query`.
+    if config.allowed_outcomes_column.is_none() {
+        events_reads.extend(config.outcome_count_column.as_deref());
+    }
+    events_reads.extend(config.min_confirmations_column.as_deref());
+    events_writes.extend(config.resolved_by_column.as_deref());
+    events_writes.extend(config.indexed_by_version_column.as_deref());
+    let market_id = config.market_identity.market_id();
+    events_reads.extend(market_id.map(|market_id| market_id.events_column.as_str()));
+
+    let mut tables = Vec::new();
+    match &config.resolve_function {
+        Some(function) => {
+            tables.push(TableUsage::new("events", &events_reads, &[], None));
+            tables.push(TableUsage {
+                when: Some("resolutions are written by this function"),
+                ..TableUsage::new(function, &[], &[], None)
+            });
+        }
+        None => {
+            tables.push(TableUsage::new(
+                "events",
+                &events_reads,
+                &events_writes,
+                None,
+            ));
+            let mut bets_reads = vec!["event_address", "bet"];
+            bets_reads.extend(market_id.map(|market_id| market_id.bets_column.as_str()));
+            let mut bets_writes = vec!["is_claimable"];
+            bets_writes.extend(config.refund_eligible_column.as_deref());
+            if let Some((amount, stake)) = &config.refund_amount {
+                bets_reads.push(stake);
+                bets_writes.push(amount);
+            }
+            tables.push(TableUsage::new("bets", &bets_reads, &bets_writes, None));
+        }
+    }
+
+    let mut cursor_writes = vec!["last_processed_block"];
+    if config.track_fetch_cursor {
+        cursor_writes.push("last_fetched_block");
+    }
+    tables.push(TableUsage::new(
+        "block_state_trunks",
+        &cursor_writes,
+        &cursor_writes,
+        None,
+    ));
+    tables.push(TableUsage::new(
+        "failed_events",
+        &[],
+        &[
+            "contract_address",
+            "block_number",
+            "transaction_hash",
+            "keys",
+            "data",
+            "reason",
+        ],
+        Some("an event is rejected"),
+    ));
+    if config.index_block_metadata {
+        let blocks_reads: &[&str] = if config.track_l1_acceptance {
+            &["block_number", "accepted_on_l1"]
+        } else {
+            &[]
+        };
+        tables.push(TableUsage::new(
+            "blocks",
+            blocks_reads,
+            &[
+                "block_number",
+                "block_hash",
+                "block_timestamp",
+                "event_count",
+                "indexed_at",
+                "accepted_on_l1",
+                "accepted_on_l1_at",
+            ],
+            None,
+        ));
+    }
+    if config.streams_pages() {
+        tables.push(TableUsage::new(
+            "page_cursors",
+            &["continuation_token"],
+            &[
+                "contract_address",
+                "block_number",
+                "continuation_token",
+                "updated_at",
+            ],
+            None,
+        ));
+    }
+    if config.recent_blocks_depth > 0 {
+        tables.push(TableUsage::new(
+            "recent_blocks",
+            &["block_number", "block_hash"],
+            &["block_number", "block_hash"],
+            None,
+        ));
+    }
+    if config.content_hash_window > 0 {
+        tables.push(TableUsage::new(
+            "block_contents",
+            &["block_number", "contract_address", "content_hash"],
+            &["block_number", "contract_address", "content_hash"],
+            None,
+        ));
+    }
+    if config.heartbeat_instance.is_some() {
+        tables.push(TableUsage::new(
+            "heartbeat",
+            &[],
+            &["instance_name", "last_loop_at", "last_processed_block"],
+            None,
+        ));
+    }
+    if config.settings_refresh_interval.is_some() {
+        tables.push(TableUsage::new(
+            "indexer_settings",
+            &["name", "value"],
+            &[],
+            None,
+        ));
+    }
+    if config.resolution_webhook_outbox && config.resolution_webhook_url.is_some() {
+        tables.push(TableUsage::new(
+            "outbox",
+            &["id", "payload", "attempts", "next_attempt_at"],
+            &["payload", "attempts", "last_error", "next_attempt_at"],
+            None,
+        ));
+    }
+    if config.quarantine_after_failures.is_some() {
+        tables.push(TableUsage::new(
+            "quarantined_contracts",
+            &["contract_address", "next_block"],
+            &["contract_address", "next_block", "reason"],
+            Some("a contract keeps failing the same block"),
+        ));
+    }
+    if config.buffer_orphan_resolutions {
+        let columns = [
+            "event_address",
+            "event_outcome",
+            "event_timestamp",
+            "transaction_hash",
+            "block_number",
+            "resolved_by",
+        ];
+        tables.push(TableUsage::new(
+            "pending_resolutions",
+            &columns,
+            &[&columns[..], &["buffered_at"][..]].concat(),
+            Some("a resolution matches no events row"),
+        ));
+    }
+    if matches!(config.digest_log, Some(DigestSink::Table)) {
+        tables.push(TableUsage::new(
+            "block_digests",
+            &["id", "digest"],
+            &[
+                "block_number",
+                "block_hash",
+                "event_count",
+                "changes_hash",
+                "prev_digest",
+                "digest",
+                "algorithm",
+            ],
+            None,
+        ));
+    }
+    tables.push(TableUsage::new(
+        "processed_ranges",
+        &["from_block", "to_block"],
+        &["from_block", "to_block", "completed_at"],
+        Some("reindex-ranges runs"),
+    ));
+
+    for route in &ctx.routes {
+        let mut columns: Vec<&str> = route
+            .columns
+            .iter()
+            .map(|mapping| mapping.column.as_str())
+            .collect();
+        columns.extend(route.block_number_column.as_deref());
+        columns.extend(route.transaction_hash_column.as_deref());
+        tables.push(TableUsage {
+            when: Some("a routed event arrives"),
+            ..TableUsage::new(&route.table, &[], &columns, None)
+        });
+    }
+
+    tables
+}
+
+fn describe_schema(ctx: &Context, json: bool) {
+    let tables = schema_usage(ctx);
+
+    if json {
+        let output = serde_json::json!({
+            "tables": tables,
+            "routes": ctx.routes,
+        });
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&output).expect("Failed to serialize schema")
+        );
+        return;
+    }
+
+    println!("Tables:");
+    for table in &tables {
+        match table.when {
+            Some(when) => println!("  {} (when {})", table.table, when),
+            None => println!("  {}", table.table),
+        }
+        if !table.reads.is_empty() {
+            println!("    reads:  {}", table.reads.join(", "));
+        }
+        if !table.writes.is_empty() {
+            println!("    writes: {}", table.writes.join(", "));
+        }
+    }
+
+    println!("Routes ({}):", ctx.routes.len());
+    for route in &ctx.routes {
+        println!("  {} => {}", route.event, route.table);
+        for mapping in &route.columns {
+            println!(
+                "    data[{}] as {:?} => {}",
+                mapping.index, mapping.kind, mapping.column
+            );
+        }
+        if let Some(column) = &route.block_number_column {
+            println!("    block number => {}", column);
+        }
+        if let Some(column) = &route.transaction_hash_column {
+            println!("    transaction hash => {}", column);
+        }
+    }
+}
+
+/// For each listed address (one per line, `#` comments allowed), finds the
+/// latest EventTimeout its contract emitted and re-applies it with the usual
+/// idempotent updates. Unlike the main loop this also covers events that
+/// are no longer active.
+async fn reindex_events(ctx: &Context, path: &str) {
+    if ctx.config.market_identity.market_id().is_some() {
+        // The file lists contracts, which don't pick out a single market.
+        error!("❌ reindex-events is not supported with MARKET_IDENTITY=composite");
+        std::process::exit(1);
+    }
+    let contents =
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+    let addresses: Vec<&str> = contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let contracts = fetch_contract_addresses(&ctx.control_pool, &ctx.config, true).await;
+    let latest_block = ctx
+        .provider
+        .block_number()
+        .await
+        .expect("Failed to get latest block number");
+
+    let (mut reapplied, mut missing) = (0, 0);
+    for address in addresses {
+        let Ok(contract_address) = Felt::from_hex(address) else {
+            println!("{}: invalid address, skipped", address);
+            missing += 1;
+            continue;
+        };
+        let event_address = format_address(&contract_address.to_fixed_hex_string());
+
+        let Some(contract) = contracts
+            .iter()
+            .find(|contract| contract.address == contract_address)
+        else {
+            println!("{}: not in the events table, skipped", event_address);
+            missing += 1;
+            continue;
+        };
+
+        // Only blocks with the contract's usual confirmations, so a
+        // resolution that may still be reorged away isn't applied.
+        let to_block = latest_block.saturating_sub(contract.confirmations(&ctx.config));
+        let mut resolution =
+            match find_resolution(ctx, contract_address, &event_address, to_block).await {
+                Ok(Some(resolution)) => resolution,
+                Ok(None) => {
+                    println!(
+                        "{}: no EventTimeout found on-chain up to block {}",
+                        event_address, to_block
+                    );
+                    missing += 1;
+                    continue;
+                }
+                Err(e) => {
+                    error!("❌ Failed to fetch events for {}: {}", event_address, e);
+                    missing += 1;
+                    continue;
+                }
+            };
+        set_resolved_by(ctx, &mut resolution).await;
+
+        let mut tx = ctx.pool.begin().await.expect("Failed to start transaction");
+        let result = update_database_for_event_finished(&resolution, &mut tx, &ctx.config).await;
+        match result {
+            Ok(written) => {
+                tx.commit().await.expect("Failed to commit");
+                if written == Written::Applied {
+                    after_commit(ctx, std::slice::from_ref(&resolution));
+                }
+                println!(
+                    "{}: re-applied outcome {} from block {:?}",
+                    event_address, resolution.event_outcome, resolution.block_number
+                );
+                reapplied += 1;
+            }
+            Err(e) => {
+                error!("❌ Failed to re-apply {}: {}", event_address, e);
+                missing += 1;
+            }
+        }
+    }
+
+    println!(
+        "{} resolution(s) re-applied, {} skipped.",
+        reapplied, missing
+    );
+}
+
+/// Scans the contract's history up to `to_block` for EventTimeout and
+/// returns the last one resolving `event_address`.
+async fn find_resolution(
+    ctx: &Context,
+    contract_address: Felt,
+    event_address: &str,
+    to_block: u64,
+) -> Result<Option<crate::EventTimeout>, ProviderError> {
+    let filter = EventFilter {
+        from_block: Some(BlockId::Number(0)),
+        to_block: Some(BlockId::Number(to_block)),
+        address: Some(contract_address),
+        keys: Some(vec![vec![event_timeout_event_key()]]),
+    };
+
+    let layout = abi::layout(ctx, contract_address).await;
+    let mut resolution = None;
+    let mut continuation_token = None;
+    loop {
+        let page = ctx
+            .provider
+            .get_events(filter.clone(), continuation_token, EVENTS_CHUNK_SIZE)
+            .await?;
+
+        for event in &page.events {
+            if let Some(parsed) = parse_event_finished_event(&event.data, event, &layout, None) {
+                if parsed.event_address == event_address {
+                    resolution = Some(parsed);
+                }
+            }
+        }
+
+        continuation_token = page.continuation_token;
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(resolution)
+}
@@ -0,0 +1,751 @@
+use crate::consistency::RetryConsistency;
+use crate::digest_log::{DigestAlgorithm, DigestSink};
+use crate::layout::EventLayout;
+use crate::metrics::MetricsBackend;
+use starknet::core::types::Felt;
+use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// "trunks" in ASCII.
+const DEFAULT_SETUP_LOCK_KEY: i64 = 0x7472_756e_6b73;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Poll for new blocks forever.
+    Follow,
+    /// Process until the cursor reaches the tip, then exit.
+    Catchup,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "follow" => Ok(Mode::Follow),
+            "catchup" => Ok(Mode::Catchup),
+            other => Err(format!("unknown mode '{}'", other)),
+        }
+    }
+}
+
+/// How a resolution marks the `events` row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolutionModel {
+    /// Set `is_active = false`.
+    Flip,
+    /// Set `resolved_at` and `status`, leaving `is_active` untouched so the
+    /// row keeps its historical state.
+    Soft,
+}
+
+impl ResolutionModel {
+    /// `events` rows still awaiting a resolution.
+    pub fn active_filter(&self) -> &'static str {
+        match self {
+            ResolutionModel::Flip => "is_active = true",
+            ResolutionModel::Soft => "is_active = true AND resolved_at IS NULL",
+        }
+    }
+
+    pub fn resolved_filter(&self) -> &'static str {
+        match self {
+            ResolutionModel::Flip => "is_active = false",
+            ResolutionModel::Soft => "resolved_at IS NOT NULL",
+        }
+    }
+
+    /// SET clause applied by a resolution.
+    pub fn mark_resolved(&self) -> &'static str {
+        match self {
+            ResolutionModel::Flip => "is_active = FALSE",
+            ResolutionModel::Soft => {
+                "resolved_at = COALESCE(resolved_at, now()), status = 'resolved'"
+            }
+        }
+    }
+}
+
+impl FromStr for ResolutionModel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "flip" => Ok(ResolutionModel::Flip),
+            "soft" => Ok(ResolutionModel::Soft),
+            other => Err(format!("unknown resolution model '{}'", other)),
+        }
+    }
+}
+
+/// How a resolution identifies its market.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketIdentity {
+    /// One contract per market: rows are matched on the event address.
+    Simple,
+    /// Several markets per contract: rows are matched on the event address
+    /// and a market id decoded from the event.
+    Composite(MarketId),
+}
+
+/// Where the market id of a composite identity lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketId {
+    /// Index of the market id in the EventTimeout `data`.
+    pub data_index: usize,
+    pub events_column: String,
+    pub bets_column: String,
+}
+
+impl MarketIdentity {
+    pub fn market_id(&self) -> Option<&MarketId> {
+        match self {
+            MarketIdentity::Simple => None,
+            MarketIdentity::Composite(market_id) => Some(market_id),
+        }
+    }
+}
+
+/// Which `bets.bet` value wins for a given outcome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BetMapping {
+    /// Outcome `1` pays `bet = 1`, any other outcome pays `bet = 0`.
+    Binary,
+    /// Outcome `N` pays `bet = N`, for markets with more than two outcomes.
+    Direct,
+}
+
+impl BetMapping {
+    pub fn winning_bet(&self, outcome: u8) -> i32 {
+        match self {
+            BetMapping::Binary => i32::from(outcome == 1),
+            BetMapping::Direct => i32::from(outcome),
+        }
+    }
+
+    /// SQL equivalent of `winning_bet` over an outcome expression.
+    pub fn winning_bet_sql(&self, outcome: &str) -> String {
+        match self {
+            BetMapping::Binary => format!("CASE WHEN {}::TEXT = '1' THEN 1 ELSE 0 END", outcome),
+            BetMapping::Direct => format!("{}::TEXT::INTEGER", outcome),
+        }
+    }
+}
+
+impl FromStr for BetMapping {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "binary" => Ok(BetMapping::Binary),
+            "direct" => Ok(BetMapping::Direct),
+            other => Err(format!("unknown bet mapping '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub mode: Mode,
+    /// Upper bound on passes in catch-up mode, in case the tip keeps moving.
+    pub catchup_max_iterations: u32,
+    /// Give up waiting for the companion service's tables after this long.
+    /// `None` waits forever.
+    pub table_wait_timeout: Option<Duration>,
+    /// Number of resolutions applied per transaction.
+    pub batch_commit_size: usize,
+    /// Also commit once the pending batch spans this many blocks.
+    pub batch_commit_blocks: Option<u64>,
+    /// Second RPC whose `get_events` results are compared against the
+    /// primary endpoint, without affecting writes.
+    pub shadow_rpc_endpoint: Option<String>,
+    /// Database function called as `fn(event_address, outcome, timestamp)`
+    /// instead of the inline UPDATEs.
+    pub resolve_function: Option<String>,
+    /// Skip event addresses resolved within this window (or already
+    /// inactive in the DB). `None` disables deduplication.
+    pub dedup_window: Option<Duration>,
+    /// How many times a failing block fetch or commit is retried within the
+    /// same pass before it's left for the next one.
+    pub in_pass_retries: u32,
+    pub in_pass_retry_backoff: Duration,
+    /// Upsert each processed block's hash, timestamp and event count into
+    /// the `blocks` table.
+    pub index_block_metadata: bool,
+    /// Recheck indexed blocks until they're accepted on L1, see
+    /// `blocks::backfill_l1_acceptance`.
+    pub track_l1_acceptance: bool,
+    /// Apply each page of events as it arrives, persisting a sub-cursor,
+    /// instead of collecting every page of a block first.
+    pub stream_pages: bool,
+    /// Column of `events` holding a human-readable market name for logs.
+    pub event_label_column: Option<String>,
+    /// Size of the main pool used for event writes.
+    pub db_max_connections: u32,
+    /// Connections reserved in a separate pool for the cursor and contract
+    /// list. `0` shares the main pool.
+    pub control_pool_connections: u32,
+    /// Outcome values a resolution may carry; anything else is routed to
+    /// `failed_events` instead of being applied. `None` accepts any outcome.
+    pub allowed_outcomes: Option<Vec<i64>>,
+    /// Column of `events` with a per-contract allowed-outcome array.
+    pub allowed_outcomes_column: Option<String>,
+    /// Blocks a contract's events must be buried under before they're
+    /// processed.
+    pub confirmations: u64,
+    /// Column of `events` with a per-contract override of `confirmations`.
+    pub min_confirmations_column: Option<String>,
+    /// On passes spanning at least `skip_resolved_min_blocks` blocks, skip
+    /// events whose address was already resolved before the pass started.
+    pub skip_resolved_at_fetch: bool,
+    pub skip_resolved_min_blocks: u64,
+    /// Column of `events` the winning outcome is written to, and the SQL
+    /// type it's cast to.
+    pub outcome_column: String,
+    pub outcome_column_type: String,
+    /// JSON file mapping extra events to tables, see `routing::Route`.
+    pub routing_config: Option<String>,
+    /// Quick retries for serialization failures and deadlocks in the write
+    /// path.
+    pub serialization_retries: u32,
+    /// Instance name for the `heartbeat` row, when heartbeats are enabled.
+    pub heartbeat_instance: Option<String>,
+    /// Number of recent block hashes kept for reorg detection. `0` disables
+    /// it.
+    pub recent_blocks_depth: u64,
+    /// Number of recent blocks whose per-contract event digests are
+    /// rechecked each pass, a lighter reorg check. `0` disables it.
+    pub content_hash_window: u64,
+    /// Poll interval in follow mode while there are no active contracts.
+    /// `None` keeps the normal interval.
+    pub idle_poll_interval: Option<Duration>,
+    /// Postgres channel that wakes an idle indexer up early, e.g. from a
+    /// trigger on `events`.
+    pub idle_notify_channel: Option<String>,
+    /// Contracts whose EventTimeout may lack outcome data, completed from
+    /// the transaction receipt or `outcome_view_function`.
+    pub resolution_fallback_contracts: Vec<Felt>,
+    /// View function called for the outcome of fallback contracts instead
+    /// of reading the receipt.
+    pub outcome_view_function: Option<String>,
+    /// Persist the last fetched block separately from the committed cursor.
+    pub track_fetch_cursor: bool,
+    /// On startup, skip straight to the fetch cursor instead of replaying
+    /// from the committed one.
+    pub resume_from_fetch_cursor: bool,
+    /// Webhook receiving deadletter alerts for events routed to
+    /// `failed_events`.
+    pub alert_webhook_url: Option<String>,
+    pub alert_min_interval: Duration,
+    /// Apply each transaction's resolutions in a single DB transaction,
+    /// never splitting one across batch commits.
+    pub tx_atomic_commit: bool,
+    pub resolution_model: ResolutionModel,
+    /// Send each block's `get_events` calls as JSON-RPC batches of at most
+    /// `rpc_batch_size`.
+    pub rpc_batch: bool,
+    pub rpc_batch_size: usize,
+    /// How often `indexer_settings` is re-read. `None` ignores the table.
+    pub settings_refresh_interval: Option<Duration>,
+    /// Largest backward cursor move allowed without `allow_cursor_rewind`.
+    pub max_cursor_rewind: u64,
+    pub allow_cursor_rewind: bool,
+    /// Webhook notified of every committed resolution, whose body is signed
+    /// with `resolution_webhook_secret` when set.
+    pub resolution_webhook_url: Option<String>,
+    pub resolution_webhook_secret: Option<String>,
+    pub resolution_webhook_queue: usize,
+    /// Persist notifications in the `outbox` table, in the resolution's
+    /// transaction, instead of the in-memory queue.
+    pub resolution_webhook_outbox: bool,
+    /// Re-send every resolved event on startup, `replay_rate` a second.
+    pub replay_on_startup: bool,
+    pub replay_rate: u32,
+    /// Where EventTimeout's outcome and timestamp are read from in `data`.
+    pub event_layout: EventLayout,
+    /// Sort each block's events by transaction index once every page is in.
+    /// Takes precedence over `stream_pages`.
+    pub canonical_event_order: bool,
+    /// Above this many blocks of lag, per-block INFO logs are only written
+    /// for every `log_sample_every`th block. `None` always logs everything.
+    pub log_sample_lag: Option<u64>,
+    pub log_sample_every: u64,
+    /// Alert once the cursor falls this many blocks behind the tip, and
+    /// again once it is back within `lag_clear_blocks`.
+    pub lag_alert_blocks: Option<u64>,
+    pub lag_clear_blocks: Option<u64>,
+    /// End a pass at the first block boundary after this long, committing
+    /// what was done, and pick up from there on the next one.
+    pub pass_time_budget: Option<Duration>,
+    /// Column of `events` the resolving transaction's sender is written to.
+    /// Costs one transaction lookup per resolving transaction.
+    pub resolved_by_column: Option<String>,
+    /// Column of `events` the indexer version applying a resolution is
+    /// written to, to find events processed by a bad release.
+    pub indexed_by_version_column: Option<String>,
+    /// Pause passes while replica replay lag exceeds this.
+    pub max_replication_lag: Option<Duration>,
+    /// Refuse to start further than this behind the tip without
+    /// `--confirm-large-backfill`. `None` never refuses.
+    pub max_startup_lag_blocks: Option<u64>,
+    pub bet_mapping: BetMapping,
+    pub market_identity: MarketIdentity,
+    /// Outcome meaning the market was cancelled: every bet is refunded and
+    /// the outcome always passes validation.
+    pub cancel_outcome: Option<u8>,
+    /// Whether a cancellation marks bets claimable. Turning it off needs a
+    /// refund column to write instead.
+    pub cancel_marks_claimable: bool,
+    /// Column of `bets` set to TRUE on every bet of a cancelled event.
+    pub refund_eligible_column: Option<String>,
+    /// `(amount column, stake column)`: on cancellation the amount column
+    /// receives the stake minus `refund_fee_bps`.
+    pub refund_amount: Option<(String, String)>,
+    pub refund_fee_bps: u32,
+    /// Column of `events` with each contract's number of outcomes `N`;
+    /// outcomes `0..N` are allowed. `allowed_outcomes_column` wins if both
+    /// are set.
+    pub outcome_count_column: Option<String>,
+    /// Before marking bets claimable, log those whose `bet` value is
+    /// outside the event's outcome range.
+    pub validate_bet_values: bool,
+    pub metrics_backend: MetricsBackend,
+    /// After a contract fails the same block this many passes in a row, it
+    /// is quarantined and caught up separately while the cursor advances.
+    /// `None` always holds the block back.
+    pub quarantine_after_failures: Option<u32>,
+    /// Hold resolutions matching no events row in `pending_resolutions` and
+    /// apply them once the row exists, instead of dropping them.
+    pub buffer_orphan_resolutions: bool,
+    /// Derive each contract's EventTimeout layout from its class ABI,
+    /// falling back to `event_layout` when that fails.
+    pub abi_decoding: bool,
+    pub abi_cache_path: Option<String>,
+    /// EventTimeout members holding the outcome and timestamp in the ABI.
+    pub abi_outcome_member: String,
+    pub abi_timestamp_member: String,
+    pub retry_consistency: RetryConsistency,
+    /// Exit with an error on the first parse failure, out-of-range event or
+    /// resolution that matches no row, instead of logging and continuing.
+    pub strict_mode: bool,
+    /// Advisory lock key startup setup runs under. Instances sharing a
+    /// database must use the same key.
+    pub setup_lock_key: i64,
+    /// Also print the end-of-run summary to stdout as a JSON line.
+    pub session_summary_stdout: bool,
+    /// Check at startup that the RPC applies `get_events` key filters.
+    pub check_key_filter: bool,
+    /// Run the audit pass this many blocks behind the main cursor. `None`
+    /// disables it.
+    pub audit_lag_blocks: Option<u64>,
+    /// Re-apply resolutions the audit pass finds missing or wrong.
+    pub audit_reconcile: bool,
+    /// Fraction of the active contracts (e.g. `0.9`) whose disappearance in
+    /// one pass is reported as a likely bug. `None` disables the check.
+    pub contract_drop_threshold: Option<f64>,
+    /// Smallest previous count the drop check applies to.
+    pub contract_drop_min_previous: usize,
+    /// Stop processing while the count stays dropped.
+    pub pause_on_contract_drop: bool,
+    /// JSON file of events to write as time-series points, see
+    /// `timeseries::Series`.
+    pub timeseries_config: Option<String>,
+    /// InfluxDB line-protocol write endpoint, and its API token.
+    pub timeseries_url: Option<String>,
+    pub timeseries_token: Option<String>,
+    pub timeseries_batch_size: usize,
+    /// Append a hash-chained digest of every processed block to this sink.
+    pub digest_log: Option<DigestSink>,
+    pub digest_algorithm: DigestAlgorithm,
+    /// Where the header and sender caches are saved on shutdown and
+    /// restored from on startup.
+    pub snapshot_path: Option<String>,
+    /// Snapshots older than this are ignored.
+    pub snapshot_max_age: Duration,
+    /// `host:port` the Prometheus endpoint is served on. Loopback by default,
+    /// since the endpoint has no authentication.
+    pub prometheus_addr: String,
+    /// `host:port` StatsD listens on, and the prefix for every metric name.
+    pub statsd_addr: String,
+    pub statsd_prefix: String,
+}
+
+impl Config {
+    /// Streaming applies pages as they arrive, which canonical ordering
+    /// can't do.
+    pub fn streams_pages(&self) -> bool {
+        self.stream_pages && !self.canonical_event_order
+    }
+
+    /// SET list applied to every bet of a cancelled event.
+    pub fn refund_assignments(&self) -> String {
+        let mut assignments = Vec::new();
+        if self.cancel_marks_claimable {
+            assignments.push("is_claimable = TRUE".to_string());
+        }
+        if let Some(column) = &self.refund_eligible_column {
+            assignments.push(format!("{} = TRUE", column));
+        }
+        if let Some((amount, stake)) = &self.refund_amount {
+            assignments.push(format!(
+                "{} = {} * {} / 10000",
+                amount,
+                stake,
+                10_000 - self.refund_fee_bps
+            ));
+        }
+        assignments.join(", ")
+    }
+
+    /// `AND` clause matching `column` against the market id bound as
+    /// `$param`, or nothing for the simple identity.
+    pub fn market_filter(&self, column: impl Fn(&MarketId) -> &str, param: usize) -> String {
+        match self.market_identity.market_id() {
+            Some(market_id) => format!(" AND {}::TEXT = ${}", column(market_id), param),
+            None => String::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Config {
+            mode: env_parse("MODE").unwrap_or(Mode::Follow),
+            catchup_max_iterations: env_parse("CATCHUP_MAX_ITERATIONS").unwrap_or(1000),
+            table_wait_timeout: env_parse::<u64>("TABLE_WAIT_TIMEOUT_SECS")
+                .map(Duration::from_secs),
+            batch_commit_size: env_parse::<usize>("BATCH_COMMIT_SIZE").unwrap_or(1).max(1),
+            batch_commit_blocks: env_parse::<u64>("BATCH_COMMIT_BLOCKS").filter(|&n| n > 0),
+            shadow_rpc_endpoint: var("SHADOW_RPC_ENDPOINT").ok(),
+            resolve_function: var("RESOLVE_FUNCTION")
+                .ok()
+                .map(|name| sql_identifier("RESOLVE_FUNCTION", name)),
+            dedup_window: env_parse::<u64>("DEDUP_WINDOW_SECS").map(Duration::from_secs),
+            in_pass_retries: env_parse("IN_PASS_RETRIES").unwrap_or(0),
+            in_pass_retry_backoff: Duration::from_millis(
+                env_parse("IN_PASS_RETRY_BACKOFF_MS").unwrap_or(500),
+            ),
+            index_block_metadata: env_parse("INDEX_BLOCK_METADATA").unwrap_or(false),
+            track_l1_acceptance: env_parse("TRACK_L1_ACCEPTANCE").unwrap_or(false),
+            stream_pages: env_parse("STREAM_PAGES").unwrap_or(false),
+            event_label_column: var("EVENT_LABEL_COLUMN")
+                .ok()
+                .map(|name| sql_identifier("EVENT_LABEL_COLUMN", name)),
+            db_max_connections: env_parse("DB_MAX_CONNECTIONS").unwrap_or(5),
+            control_pool_connections: env_parse("CONTROL_POOL_CONNECTIONS").unwrap_or(0),
+            allowed_outcomes: env_list("ALLOWED_OUTCOMES"),
+            allowed_outcomes_column: var("ALLOWED_OUTCOMES_COLUMN")
+                .ok()
+                .map(|name| sql_identifier("ALLOWED_OUTCOMES_COLUMN", name)),
+            confirmations: env_parse("CONFIRMATIONS").unwrap_or(0),
+            min_confirmations_column: var("MIN_CONFIRMATIONS_COLUMN")
+                .ok()
+                .map(|name| sql_identifier("MIN_CONFIRMATIONS_COLUMN", name)),
+            skip_resolved_at_fetch: env_parse("SKIP_RESOLVED_AT_FETCH").unwrap_or(false),
+            skip_resolved_min_blocks: env_parse("SKIP_RESOLVED_MIN_BLOCKS").unwrap_or(100),
+            outcome_column: sql_identifier(
+                "OUTCOME_COLUMN",
+                var("OUTCOME_COLUMN").unwrap_or_else(|_| "outcome".to_string()),
+            ),
+            outcome_column_type: outcome_column_type(
+                var("OUTCOME_COLUMN_TYPE").unwrap_or_else(|_| "integer".to_string()),
+            ),
+            routing_config: var("ROUTING_CONFIG").ok(),
+            serialization_retries: env_parse("SERIALIZATION_RETRIES").unwrap_or(5),
+            heartbeat_instance: env_parse::<bool>("HEARTBEAT")
+                .unwrap_or(false)
+                .then(|| var("INSTANCE_NAME").unwrap_or_else(|_| "trunks".to_string())),
+            recent_blocks_depth: env_parse("RECENT_BLOCKS_DEPTH").unwrap_or(0),
+            content_hash_window: env_parse("CONTENT_HASH_WINDOW").unwrap_or(0),
+            idle_poll_interval: env_parse::<u64>("IDLE_POLL_INTERVAL_SECS")
+                .map(Duration::from_secs),
+            idle_notify_channel: var("IDLE_NOTIFY_CHANNEL")
+                .ok()
+                .map(|name| sql_identifier("IDLE_NOTIFY_CHANNEL", name)),
+            resolution_fallback_contracts: env_list::<String>("RESOLUTION_FALLBACK_CONTRACTS")
+                .unwrap_or_default()
+                .iter()
+                .map(|address| {
+                    Felt::from_hex(address).unwrap_or_else(|err| {
+                        panic!("Invalid value in RESOLUTION_FALLBACK_CONTRACTS: {}", err)
+                    })
+                })
+                .collect(),
+            outcome_view_function: var("OUTCOME_VIEW_FUNCTION").ok(),
+            track_fetch_cursor: env_parse("TRACK_FETCH_CURSOR").unwrap_or(false),
+            resume_from_fetch_cursor: env_parse("RESUME_FROM_FETCH_CURSOR").unwrap_or(false),
+            alert_webhook_url: var("ALERT_WEBHOOK_URL").ok(),
+            alert_min_interval: Duration::from_secs(
+                env_parse("ALERT_MIN_INTERVAL_SECS").unwrap_or(60),
+            ),
+            tx_atomic_commit: env_parse("TX_ATOMIC_COMMIT").unwrap_or(false),
+            resolution_model: env_parse("RESOLUTION_MODEL").unwrap_or(ResolutionModel::Flip),
+            rpc_batch: env_parse("RPC_BATCH").unwrap_or(false),
+            rpc_batch_size: env_parse::<usize>("RPC_BATCH_SIZE").unwrap_or(20).max(1),
+            settings_refresh_interval: env_parse::<u64>("SETTINGS_REFRESH_SECS")
+                .map(Duration::from_secs),
+            max_cursor_rewind: env_parse("MAX_CURSOR_REWIND").unwrap_or(1000),
+            allow_cursor_rewind: env_parse("ALLOW_CURSOR_REWIND").unwrap_or(false),
+            resolution_webhook_url: var("RESOLUTION_WEBHOOK_URL").ok(),
+            resolution_webhook_secret: var("RESOLUTION_WEBHOOK_SECRET").ok(),
+            resolution_webhook_queue: env_parse::<usize>("RESOLUTION_WEBHOOK_QUEUE")
+                .unwrap_or(1000)
+                .max(1),
+            resolution_webhook_outbox: env_parse("RESOLUTION_WEBHOOK_OUTBOX").unwrap_or(false),
+            replay_on_startup: env_parse("REPLAY_ON_STARTUP").unwrap_or(false),
+            replay_rate: env_parse::<u32>("REPLAY_RATE_PER_SEC").unwrap_or(50).max(1),
+            event_layout: {
+                let default = EventLayout::default();
+                EventLayout {
+                    outcome: env_parse("OUTCOME_DECODING").unwrap_or(default.outcome),
+                    timestamp: env_parse("TIMESTAMP_DECODING").unwrap_or(default.timestamp),
+                }
+            },
+            canonical_event_order: env_parse("CANONICAL_EVENT_ORDER").unwrap_or(false),
+            log_sample_lag: env_parse("LOG_SAMPLE_LAG"),
+            log_sample_every: env_parse::<u64>("LOG_SAMPLE_EVERY").unwrap_or(100).max(1),
+            lag_alert_blocks: env_parse("LAG_ALERT_BLOCKS"),
+            lag_clear_blocks: env_parse("LAG_CLEAR_BLOCKS"),
+            pass_time_budget: env_parse::<u64>("PASS_TIME_BUDGET_SECS")
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            resolved_by_column: var("RESOLVED_BY_COLUMN")
+                .ok()
+                .map(|name| sql_identifier("RESOLVED_BY_COLUMN", name)),
+            indexed_by_version_column: var("INDEXED_BY_VERSION_COLUMN")
+                .ok()
+                .map(|name| sql_identifier("INDEXED_BY_VERSION_COLUMN", name)),
+            max_replication_lag: env_parse::<u64>("MAX_REPLICATION_LAG_SECS")
+                .map(Duration::from_secs),
+            max_startup_lag_blocks: env_parse("MAX_STARTUP_LAG_BLOCKS"),
+            bet_mapping: env_parse("BET_MAPPING").unwrap_or(BetMapping::Binary),
+            market_identity: market_identity(),
+            cancel_outcome: env_parse("CANCEL_OUTCOME"),
+            cancel_marks_claimable: cancel_marks_claimable(),
+            refund_eligible_column: var("REFUND_ELIGIBLE_COLUMN")
+                .ok()
+                .map(|name| sql_identifier("REFUND_ELIGIBLE_COLUMN", name)),
+            refund_amount: refund_amount(),
+            refund_fee_bps: env_parse::<u32>("REFUND_FEE_BPS").unwrap_or(0).min(10_000),
+            outcome_count_column: var("OUTCOME_COUNT_COLUMN")
+                .ok()
+                .map(|name| sql_identifier("OUTCOME_COUNT_COLUMN", name)),
+            validate_bet_values: env_parse("VALIDATE_BET_VALUES").unwrap_or(false),
+            metrics_backend: env_parse("METRICS_BACKEND").unwrap_or(MetricsBackend::Prometheus),
+            quarantine_after_failures: env_parse::<u32>("QUARANTINE_AFTER_FAILURES")
+                .filter(|&failures| failures > 0),
+            buffer_orphan_resolutions: env_parse("BUFFER_ORPHAN_RESOLUTIONS").unwrap_or(false),
+            abi_decoding: env_parse("ABI_DECODING").unwrap_or(false),
+            abi_cache_path: var("ABI_CACHE_PATH").ok(),
+            abi_outcome_member: var("ABI_OUTCOME_MEMBER").unwrap_or_else(|_| "outcome".to_string()),
+            abi_timestamp_member: var("ABI_TIMESTAMP_MEMBER")
+                .unwrap_or_else(|_| "timestamp".to_string()),
+            retry_consistency: env_parse("RETRY_CONSISTENCY").unwrap_or(RetryConsistency::Off),
+            strict_mode: env_parse("STRICT_MODE").unwrap_or(false),
+            setup_lock_key: env_parse("SETUP_LOCK_KEY").unwrap_or(DEFAULT_SETUP_LOCK_KEY),
+            session_summary_stdout: env_parse("SESSION_SUMMARY_STDOUT").unwrap_or(false),
+            check_key_filter: env_parse("CHECK_KEY_FILTER").unwrap_or(false),
+            audit_lag_blocks: env_parse("AUDIT_LAG_BLOCKS"),
+            audit_reconcile: env_parse("AUDIT_RECONCILE").unwrap_or(false),
+            contract_drop_threshold: env_parse::<f64>("CONTRACT_DROP_THRESHOLD")
+                .map(|threshold| threshold.clamp(0.0, 1.0)),
+            contract_drop_min_previous: env_parse("CONTRACT_DROP_MIN_PREVIOUS").unwrap_or(10),
+            pause_on_contract_drop: env_parse("PAUSE_ON_CONTRACT_DROP").unwrap_or(false),
+            timeseries_config: var("TIMESERIES_CONFIG").ok(),
+            timeseries_url: var("TIMESERIES_URL").ok(),
+            timeseries_token: var("TIMESERIES_TOKEN").ok(),
+            timeseries_batch_size: env_parse::<usize>("TIMESERIES_BATCH_SIZE")
+                .unwrap_or(500)
+                .max(1),
+            digest_log: env_parse("DIGEST_LOG"),
+            digest_algorithm: env_parse("DIGEST_ALGORITHM").unwrap_or(DigestAlgorithm::Sha256),
+            snapshot_path: var("SNAPSHOT_PATH").ok(),
+            snapshot_max_age: Duration::from_secs(
+                env_parse("SNAPSHOT_MAX_AGE_SECS").unwrap_or(3600),
+            ),
+            prometheus_addr: var("PROMETHEUS_ADDR")
+                .unwrap_or_else(|_| "127.0.0.1:9464".to_string()),
+            statsd_addr: var("STATSD_ADDR").unwrap_or_else(|_| "127.0.0.1:8125".to_string()),
+            statsd_prefix: var("STATSD_PREFIX").unwrap_or_else(|_| "trunks".to_string()),
+        }
+    }
+}
+
+fn env_parse<T>(name: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    var(name).ok().map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|err| panic!("Invalid value for {}: {}", name, err))
+    })
+}
+
+/// Reads `name` from the environment. Unit tests see an empty one, so
+/// what the developer has exported can't change their results.
+fn var(name: &str) -> Result<String, env::VarError> {
+    if cfg!(test) {
+        return Err(env::VarError::NotPresent);
+    }
+    env::var(name)
+}
+
+/// Parses a comma-separated list such as `ALLOWED_OUTCOMES=0,1`.
+fn env_list<T>(name: &str) -> Option<Vec<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    var(name).ok().map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse()
+                    .unwrap_or_else(|err| panic!("Invalid value in {}: {}", name, err))
+            })
+            .collect()
+    })
+}
+
+/// Features keyed on the event address alone can't tell the markets of a
+/// contract apart, so they are refused with a composite identity.
+const ADDRESS_KEYED_OPTIONS: [&str; 3] = [
+    "RESOLVE_FUNCTION",
+    "BUFFER_ORPHAN_RESOLUTIONS",
+    "AUDIT_LAG_BLOCKS",
+];
+
+fn market_identity() -> MarketIdentity {
+    match var("MARKET_IDENTITY").as_deref() {
+        Err(_) | Ok("simple") => MarketIdentity::Simple,
+        Ok("composite") => {
+            if let Some(option) = ADDRESS_KEYED_OPTIONS.iter().find(|option| {
+                var(option).map_or(false, |value| !value.is_empty() && value != "false")
+            }) {
+                panic!("{} is not supported with MARKET_IDENTITY=composite", option);
+            }
+            MarketIdentity::Composite(MarketId {
+                data_index: env_parse("MARKET_ID_INDEX")
+                    .expect("MARKET_ID_INDEX must be set with MARKET_IDENTITY=composite"),
+                events_column: sql_identifier(
+                    "EVENTS_MARKET_ID_COLUMN",
+                    var("EVENTS_MARKET_ID_COLUMN").unwrap_or_else(|_| "market_id".to_string()),
+                ),
+                bets_column: sql_identifier(
+                    "BETS_MARKET_ID_COLUMN",
+                    var("BETS_MARKET_ID_COLUMN").unwrap_or_else(|_| "market_id".to_string()),
+                ),
+            })
+        }
+        Ok(other) => panic!(
+            "Invalid value for MARKET_IDENTITY: '{}' (expected simple or composite)",
+            other
+        ),
+    }
+}
+
+fn cancel_marks_claimable() -> bool {
+    let marks = env_parse("CANCEL_MARKS_CLAIMABLE").unwrap_or(true);
+    if !marks && var("REFUND_ELIGIBLE_COLUMN").is_err() && var("REFUND_AMOUNT_COLUMN").is_err() {
+        panic!("CANCEL_MARKS_CLAIMABLE=false needs REFUND_ELIGIBLE_COLUMN or REFUND_AMOUNT_COLUMN");
+    }
+    marks
+}
+
+fn refund_amount() -> Option<(String, String)> {
+    let amount = var("REFUND_AMOUNT_COLUMN").ok()?;
+    let stake = var("REFUND_STAKE_COLUMN")
+        .expect("REFUND_STAKE_COLUMN must be set with REFUND_AMOUNT_COLUMN");
+    Some((
+        sql_identifier("REFUND_AMOUNT_COLUMN", amount),
+        sql_identifier("REFUND_STAKE_COLUMN", stake),
+    ))
+}
+
+fn outcome_column_type(value: String) -> String {
+    const SUPPORTED: [&str; 4] = ["smallint", "integer", "bigint", "text"];
+
+    let value = value.to_ascii_lowercase();
+    if !SUPPORTED.contains(&value.as_str()) {
+        panic!(
+            "Invalid value for OUTCOME_COLUMN_TYPE: '{}' (expected one of {:?})",
+            value, SUPPORTED
+        );
+    }
+    value
+}
+
+/// Rejects anything that isn't a plain, optionally schema-qualified, SQL
+/// identifier, since these values are interpolated into queries.
+fn sql_identifier(name: &str, value: String) -> String {
+    if !value.split('.').all(is_identifier) {
+        panic!(
+            "Invalid value for {}: '{}' is not a SQL identifier",
+            name, value
+        );
+    }
+    value
+}
+
+pub fn is_identifier(value: &str) -> bool {
+    let mut chars = value.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Every option at its default, for unit tests to adjust.
+#[cfg(test)]
+impl Config {
+    pub fn for_tests() -> Self {
+        Config::from_env()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn direct_mapping_pays_the_matching_bet_in_a_four_outcome_market() {
+        for outcome in 0..4u8 {
+            assert_eq!(BetMapping::Direct.winning_bet(outcome), i32::from(outcome));
+        }
+        assert_eq!(BetMapping::Direct.winning_bet_sql("o"), "o::TEXT::INTEGER");
+    }
+
+    #[test]
+    fn refund_assignments_default_to_the_claimable_flip() {
+        let config = Config::for_tests();
+        assert_eq!(config.refund_assignments(), "is_claimable = TRUE");
+    }
+
+    #[test]
+    fn refund_assignments_add_the_flag_and_fee_adjusted_amount() {
+        let mut config = Config::for_tests();
+        config.refund_eligible_column = Some("refund_eligible".to_string());
+        config.refund_amount = Some(("refund_amount".to_string(), "stake".to_string()));
+        config.refund_fee_bps = 250;
+        assert_eq!(
+            config.refund_assignments(),
+            "is_claimable = TRUE, refund_eligible = TRUE, refund_amount = stake * 9750 / 10000"
+        );
+
+        config.cancel_marks_claimable = false;
+        assert_eq!(
+            config.refund_assignments(),
+            "refund_eligible = TRUE, refund_amount = stake * 9750 / 10000"
+        );
+    }
+
+    #[test]
+    fn binary_mapping_folds_every_other_outcome_into_bet_zero() {
+        assert_eq!(BetMapping::Binary.winning_bet(1), 1);
+        for outcome in [0, 2, 3] {
+            assert_eq!(BetMapping::Binary.winning_bet(outcome), 0);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use starknet::core::types::Felt;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Tracks contracts processed past the global cursor. The cursor can only
+/// advance to the block every contract has enough confirmations for, so
+/// contracts needing fewer run ahead of it and must not re-apply those
+/// blocks on the next pass.
+#[derive(Default)]
+pub struct ConfirmationTracker {
+    tip: AtomicU64,
+    processed_ahead: Mutex<HashMap<Felt, u64>>,
+}
+
+impl ConfirmationTracker {
+    /// Sets the chain tip confirmations are counted from for this pass.
+    pub fn set_tip(&self, tip: u64) {
+        self.tip.store(tip, Ordering::Relaxed);
+    }
+
+    /// Whether `contract`'s events in `block_number` are deep enough and
+    /// weren't already applied ahead of the cursor.
+    pub fn due(&self, contract: Felt, confirmations: u64, block_number: u64) -> bool {
+        if block_number + confirmations > self.tip.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.processed_ahead
+            .lock()
+            .unwrap()
+            .get(&contract)
+            .map_or(true, |&processed| block_number > processed)
+    }
+
+    /// Records that `contract` has been applied up to `block_number`, past
+    /// the global `cursor`.
+    pub fn processed(&self, contract: Felt, block_number: u64, cursor: u64) {
+        if block_number <= cursor {
+            return;
+        }
+        let mut processed_ahead = self.processed_ahead.lock().unwrap();
+        let processed = processed_ahead.entry(contract).or_insert(block_number);
+        *processed = (*processed).max(block_number);
+    }
+
+    /// Forgets everything processed ahead, after the cursor was rolled back
+    /// for a reorg.
+    pub fn reset(&self) {
+        self.processed_ahead.lock().unwrap().clear();
+    }
+
+    /// Forgets progress the global cursor has caught up with.
+    pub fn prune(&self, cursor: u64) {
+        self.processed_ahead
+            .lock()
+            .unwrap()
+            .retain(|_, processed| *processed > cursor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contracts_with_different_depths_run_ahead_independently() {
+        let (shallow, deep) = (Felt::ONE, Felt::TWO);
+        let tracker = ConfirmationTracker::default();
+        tracker.set_tip(100);
+
+        assert!(tracker.due(shallow, 0, 100));
+        assert!(tracker.due(deep, 10, 90));
+        assert!(!tracker.due(deep, 10, 91));
+
+        // The cursor stops at 90, where the deep contract is, while the
+        // shallow one was applied up to the tip.
+        tracker.processed(shallow, 100, 90);
+        tracker.processed(deep, 90, 90);
+        assert!(!tracker.due(shallow, 0, 95));
+
+        tracker.set_tip(105);
+        assert!(tracker.due(shallow, 0, 101));
+        assert!(tracker.due(deep, 10, 95));
+        assert!(!tracker.due(deep, 10, 96));
+    }
+
+    #[test]
+    fn a_contract_silent_since_it_was_added_stays_due_for_new_blocks() {
+        let (busy, silent) = (Felt::ONE, Felt::TWO);
+        let tracker = ConfirmationTracker::default();
+
+        // A hundred passes of ten blocks with the silent contract needing
+        // more confirmations, as `process_new_events` records them.
+        let mut cursor = 0;
+        for tip in (10..=1000).step_by(10) {
+            tracker.set_tip(tip);
+            for block_number in cursor + 1..=tip - 10 {
+                assert!(tracker.due(silent, 10, block_number));
+            }
+            cursor = tip - 10;
+            tracker.processed(busy, tip, cursor);
+            tracker.processed(silent, cursor, cursor);
+            tracker.prune(cursor);
+        }
+
+        // Its first event, long after it was added, is still picked up once
+        // deep enough, without waiting on any per-contract state.
+        tracker.set_tip(1005);
+        assert!(tracker.due(silent, 10, 995));
+        assert!(!tracker.due(silent, 10, 996));
+        assert!(!tracker.due(busy, 0, 1000));
+        assert!(tracker.due(busy, 0, 1001));
+    }
+
+    #[test]
+    fn reset_and_prune_forget_progress_ahead_of_the_cursor() {
+        let contract = Felt::ONE;
+        let tracker = ConfirmationTracker::default();
+        tracker.set_tip(100);
+        tracker.processed(contract, 100, 90);
+
+        tracker.prune(95);
+        assert!(!tracker.due(contract, 0, 96));
+        tracker.prune(100);
+        assert!(tracker.due(contract, 0, 96));
+
+        tracker.processed(contract, 100, 90);
+        tracker.reset();
+        assert!(tracker.due(contract, 0, 96));
+    }
+}
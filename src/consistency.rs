@@ -0,0 +1,81 @@
+use starknet::core::types::{EmittedEvent, Felt};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// Digests kept in memory; older block numbers are evicted first.
+const DIGEST_CACHE_SIZE: usize = 4096;
+
+/// What to do when a block is fetched again in the same session, e.g. on a
+/// retry, and the RPC returns different events than the previous attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryConsistency {
+    Off,
+    /// Log a warning and use the latest result.
+    Warn,
+    /// Also hold the block back until two attempts in a row agree or the
+    /// block is accepted on L1.
+    Finalized,
+}
+
+impl FromStr for RetryConsistency {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" => Ok(RetryConsistency::Off),
+            "warn" => Ok(RetryConsistency::Warn),
+            "finalized" => Ok(RetryConsistency::Finalized),
+            other => Err(format!("unknown retry consistency mode '{}'", other)),
+        }
+    }
+}
+
+/// Digest of the events last fetched for each block and contract.
+#[derive(Default)]
+pub struct FetchDigests {
+    digests: Mutex<BTreeMap<(u64, Felt), u64>>,
+}
+
+impl FetchDigests {
+    /// Stores `digest` for the block and returns the one it replaces.
+    pub fn replace(&self, contract: Felt, block_number: u64, digest: u64) -> Option<u64> {
+        let mut digests = self.digests.lock().unwrap();
+        let previous = digests.insert((block_number, contract), digest);
+        while digests.len() > DIGEST_CACHE_SIZE {
+            digests.pop_first();
+        }
+        previous
+    }
+
+    pub fn get(&self, contract: Felt, block_number: u64) -> Option<u64> {
+        self.digests
+            .lock()
+            .unwrap()
+            .get(&(block_number, contract))
+            .copied()
+    }
+}
+
+/// Order-independent digest of an event set, so pages served in a
+/// different order don't count as a difference.
+pub fn digest(events: &[EmittedEvent]) -> u64 {
+    let mut hashes: Vec<u64> = events
+        .iter()
+        .map(|event| {
+            let mut hasher = DefaultHasher::new();
+            event.transaction_hash.hash(&mut hasher);
+            event.from_address.hash(&mut hasher);
+            event.keys.hash(&mut hasher);
+            event.data.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect();
+    hashes.sort_unstable();
+
+    let mut hasher = DefaultHasher::new();
+    hashes.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Resolutions applied within the last `window`, keyed by
+/// `EventTimeout::dedup_key`, so chatty contracts that re-emit EventTimeout
+/// don't trigger the same UPDATEs over and over.
+pub struct ResolvedCache {
+    window: Duration,
+    resolved_at: Mutex<HashMap<String, Instant>>,
+}
+
+impl ResolvedCache {
+    pub fn new(window: Duration) -> Self {
+        ResolvedCache {
+            window,
+            resolved_at: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        let mut resolved_at = self.resolved_at.lock().unwrap();
+        resolved_at.retain(|_, at| at.elapsed() < self.window);
+        resolved_at.contains_key(key)
+    }
+
+    pub fn insert(&self, key: &str) {
+        self.resolved_at
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), Instant::now());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::{Context, EventTimeout};
+use log::{error, info};
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use sqlx::{Pool, Postgres};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::str::FromStr;
+use tokio::sync::Mutex;
+
+/// Where the digest chain is appended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DigestSink {
+    /// The `block_digests` table.
+    Table,
+    /// One JSON record per line in the given file.
+    File(String),
+}
+
+impl FromStr for DigestSink {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "" => Err("empty digest log".to_string()),
+            "table" => Ok(DigestSink::Table),
+            path => Ok(DigestSink::File(path.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl FromStr for DigestAlgorithm {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(DigestAlgorithm::Sha256),
+            "sha512" => Ok(DigestAlgorithm::Sha512),
+            other => Err(format!("unknown digest algorithm '{}'", other)),
+        }
+    }
+}
+
+impl DigestAlgorithm {
+    fn name(self) -> &'static str {
+        match self {
+            DigestAlgorithm::Sha256 => "sha256",
+            DigestAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    fn hash(self, bytes: &[u8]) -> String {
+        match self {
+            DigestAlgorithm::Sha256 => hex::encode(Sha256::digest(bytes)),
+            DigestAlgorithm::Sha512 => hex::encode(Sha512::digest(bytes)),
+        }
+    }
+
+    /// The `prev_digest` of the first record.
+    fn genesis(self) -> String {
+        "0".repeat(self.hash(b"").len())
+    }
+}
+
+/// What a pass applied for one block, before it is committed to the log.
+pub struct BlockChanges {
+    pub block_number: u64,
+    pub event_count: usize,
+    changes: Vec<u8>,
+}
+
+impl BlockChanges {
+    pub fn new(block_number: u64) -> Self {
+        BlockChanges {
+            block_number,
+            event_count: 0,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Adds a resolution about to be applied, in application order.
+    pub fn push(&mut self, event: &EventTimeout) {
+        self.changes.extend_from_slice(
+            format!(
+                "{}:{}:{}\n",
+                event.identity(),
+                event.event_outcome,
+                event.transaction_hash.to_fixed_hex_string()
+            )
+            .as_bytes(),
+        );
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    block_number: u64,
+    block_hash: Option<String>,
+    event_count: usize,
+    changes_hash: String,
+    prev_digest: &'a str,
+    digest: &'a str,
+    algorithm: &'static str,
+}
+
+/// Appends a record per committed block, each hashing its fields together
+/// with the previous record's digest, so that rewriting, dropping or
+/// reordering any record breaks every digest after it.
+pub struct DigestLog {
+    sink: DigestSink,
+    algorithm: DigestAlgorithm,
+    /// Digest of the last record written.
+    last: Mutex<String>,
+}
+
+impl DigestLog {
+    /// Picks the chain up from its last record, if any.
+    pub async fn open(
+        sink: DigestSink,
+        algorithm: DigestAlgorithm,
+        pool: &Pool<Postgres>,
+    ) -> Result<Self, String> {
+        let last = match &sink {
+            DigestSink::Table => {
+                sqlx::query_scalar("SELECT digest FROM block_digests ORDER BY id DESC LIMIT 1")
+                    .fetch_optional(pool)
+                    .await
+                    .map_err(|e| e.to_string())?
+            }
+            DigestSink::File(path) => match fs::read_to_string(path) {
+                Ok(contents) => match contents.lines().last() {
+                    Some(line) => Some(
+                        serde_json::from_str::<serde_json::Value>(line)
+                            .ok()
+                            .and_then(|record| record["digest"].as_str().map(str::to_string))
+                            .ok_or_else(|| format!("unreadable last record in {}", path))?,
+                    ),
+                    None => None,
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(format!("failed to read {}: {}", path, e)),
+            },
+        };
+
+        info!(
+            "🧾 Appending {} block digests to {:?}",
+            algorithm.name(),
+            sink
+        );
+        Ok(DigestLog {
+            last: Mutex::new(last.unwrap_or_else(|| algorithm.genesis())),
+            sink,
+            algorithm,
+        })
+    }
+
+    /// Appends `blocks` in order. A record that can't be written is logged
+    /// and left out, so the chain continues from the last stored record.
+    pub async fn append(&self, ctx: &Context, blocks: &[BlockChanges]) {
+        let mut last = self.last.lock().await;
+        for block in blocks {
+            let block_hash = match ctx.headers.get(&ctx.provider, block.block_number).await {
+                Ok(header) => header.map(|header| header.block_hash.to_fixed_hex_string()),
+                Err(e) => {
+                    error!(
+                        "Failed to fetch header for block {} digest: {}",
+                        block.block_number, e
+                    );
+                    None
+                }
+            };
+            let changes_hash = self.algorithm.hash(&block.changes);
+            let digest = self.algorithm.hash(
+                format!(
+                    "{}|{}|{}|{}|{}",
+                    block.block_number,
+                    block_hash.as_deref().unwrap_or(""),
+                    block.event_count,
+                    changes_hash,
+                    last
+                )
+                .as_bytes(),
+            );
+            let record = Record {
+                block_number: block.block_number,
+                block_hash,
+                event_count: block.event_count,
+                changes_hash,
+                prev_digest: &last,
+                digest: &digest,
+                algorithm: self.algorithm.name(),
+            };
+
+            match self.write(ctx, &record).await {
+                Ok(()) => *last = digest,
+                Err(e) => error!(
+                    "Failed to append digest for block {}: {}",
+                    block.block_number, e
+                ),
+            }
+        }
+    }
+
+    async fn write(&self, ctx: &Context, record: &Record<'_>) -> Result<(), String> {
+        match &self.sink {
+            DigestSink::Table => sqlx::query(
+                "INSERT INTO block_digests
+                    (block_number, block_hash, event_count, changes_hash, prev_digest,
+                     digest, algorithm)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(record.block_number as i64)
+            .bind(&record.block_hash)
+            .bind(record.event_count as i64)
+            .bind(&record.changes_hash)
+            .bind(record.prev_digest)
+            .bind(record.digest)
+            .bind(record.algorithm)
+            .execute(&ctx.control_pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+            DigestSink::File(path) => {
+                let line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| e.to_string())?;
+                writeln!(file, "{}", line)
+                    .and_then(|()| file.sync_data())
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Mutex;
+
+/// Flags a sharp fall in the number of active contracts between passes,
+/// which usually means a bad migration or query rather than a quiet period.
+pub struct DropGuard {
+    /// Fraction of the previous count that must disappear in one pass.
+    threshold: f64,
+    /// Counts below this are too small for a drop to be meaningful.
+    min_previous: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Last healthy count.
+    baseline: Option<usize>,
+    tripped: bool,
+}
+
+pub enum Verdict {
+    Healthy,
+    /// The count fell sharply on this pass.
+    Dropped {
+        previous: usize,
+    },
+    /// Still below the threshold since an earlier drop.
+    StillDropped {
+        previous: usize,
+    },
+    /// Back above the threshold after a drop.
+    Recovered,
+}
+
+impl DropGuard {
+    pub fn new(threshold: f64, min_previous: usize) -> Self {
+        DropGuard {
+            threshold,
+            min_previous,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn check(&self, current: usize) -> Verdict {
+        let mut state = self.state.lock().unwrap();
+        if let Some(previous) = state.baseline {
+            let floor = previous as f64 * (1.0 - self.threshold);
+            if previous >= self.min_previous && (current as f64) <= floor {
+                return if std::mem::replace(&mut state.tripped, true) {
+                    Verdict::StillDropped { previous }
+                } else {
+                    Verdict::Dropped { previous }
+                };
+            }
+        }
+
+        state.baseline = Some(current);
+        if std::mem::replace(&mut state.tripped, false) {
+            Verdict::Recovered
+        } else {
+            Verdict::Healthy
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::metrics::Metric;
+use crate::{strict_violation, Context};
+use log::{error, info};
+use starknet::core::types::{EmittedEvent, Felt};
+
+/// Stores an event that won't be applied, with its raw keys and data, so it
+/// can be investigated and replayed by hand, and raises a deadletter alert
+/// when alerting is configured. Fails if the event couldn't be stored, so
+/// the caller fails its block and the event is fetched again on the next
+/// pass instead of being lost. In strict mode it fails once the event is
+/// stored, which stops the run.
+pub async fn record(ctx: &Context, event: &EmittedEvent, reason: &str) -> Result<(), sqlx::Error> {
+    ctx.metrics.count(Metric::FailedEvents, 1);
+    ctx.session.add_error();
+    if let Some(alerter) = &ctx.alerter {
+        alerter.deadletter(event, reason);
+    }
+
+    sqlx::query(
+        "INSERT INTO failed_events
+            (contract_address, block_number, transaction_hash, keys, data, reason)
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(event.from_address.to_fixed_hex_string())
+    .bind(event.block_number.map(|n| n as i64))
+    .bind(event.transaction_hash.to_fixed_hex_string())
+    .bind(to_hex(&event.keys))
+    .bind(to_hex(&event.data))
+    .bind(reason)
+    .execute(&ctx.pool)
+    .await
+    .map_err(|e| {
+        error!("Failed to record failed event: {}", e);
+        e
+    })?;
+
+    info!(
+        "Recorded failed event from tx {}: {}",
+        event.transaction_hash.to_fixed_hex_string(),
+        reason
+    );
+    strict_violation(&ctx.config, reason)
+}
+
+fn to_hex(values: &[Felt]) -> Vec<String> {
+    values.iter().map(|value| value.to_hex_string()).collect()
+}
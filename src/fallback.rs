@@ -0,0 +1,137 @@
+use crate::{Context, Contract};
+use log::{error, info};
+use starknet::core::types::{
+    BlockId, BlockTag, EmittedEvent, Event, Felt, FunctionCall, TransactionReceipt,
+};
+use starknet::core::utils::get_selector_from_name;
+use starknet::providers::{Provider, ProviderError};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const CACHE_SIZE: usize = 4096;
+
+/// Completed payloads by `(transaction_hash, contract)`, so replays and
+/// retries don't repeat the receipt or view call.
+#[derive(Default)]
+pub struct FallbackCache {
+    completed: Mutex<HashMap<(Felt, Felt), Option<Vec<Felt>>>>,
+}
+
+/// For contracts whose EventTimeout doesn't carry the full
+/// `[event_address, outcome, timestamp]` payload, rebuilds it from the
+/// configured view function or, failing that, from a fuller event the same
+/// contract emitted in the transaction receipt. Returns `None` when neither
+/// source has it.
+pub async fn complete(
+    ctx: &Context,
+    contract: &Contract,
+    event: &EmittedEvent,
+) -> Option<Vec<Felt>> {
+    let key = (event.transaction_hash, contract.address);
+    if let Some(cached) = ctx.fallback_cache.completed.lock().unwrap().get(&key) {
+        return cached.clone();
+    }
+
+    let result = match &ctx.config.outcome_view_function {
+        Some(function) => from_view(ctx, contract, event, function).await,
+        None => from_receipt(ctx, contract, event).await,
+    };
+
+    match result {
+        Ok(completed) => {
+            if let Some(data) = &completed {
+                info!(
+                    "🧾 Completed EventTimeout data for {} from tx {}: {:?}",
+                    contract.display_name(),
+                    event.transaction_hash.to_fixed_hex_string(),
+                    data
+                );
+            }
+
+            let mut cache = ctx.fallback_cache.completed.lock().unwrap();
+            if cache.len() >= CACHE_SIZE {
+                cache.clear();
+            }
+            cache.insert(key, completed.clone());
+            completed
+        }
+        Err(e) => {
+            // Not cached, so the next replay of this block tries again.
+            error!(
+                "Failed to complete EventTimeout data for {}: {}",
+                contract.display_name(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Calls `function` on the contract at the event's block. It must return
+/// the outcome, optionally followed by the resolution timestamp; without
+/// one the block timestamp is used.
+async fn from_view(
+    ctx: &Context,
+    contract: &Contract,
+    event: &EmittedEvent,
+    function: &str,
+) -> Result<Option<Vec<Felt>>, ProviderError> {
+    let block_id = match event.block_number {
+        Some(block_number) => BlockId::Number(block_number),
+        None => BlockId::Tag(BlockTag::Pending),
+    };
+    let selector = get_selector_from_name(function).expect("Invalid OUTCOME_VIEW_FUNCTION");
+
+    let result = ctx
+        .provider
+        .call(
+            FunctionCall {
+                contract_address: contract.address,
+                entry_point_selector: selector,
+                calldata: event.data.clone(),
+            },
+            block_id,
+        )
+        .await?;
+
+    let Some(outcome) = result.first().copied() else {
+        return Ok(None);
+    };
+    let timestamp = match (result.get(1), event.block_number) {
+        (Some(timestamp), _) => *timestamp,
+        (None, Some(block_number)) => match ctx.headers.get(&ctx.provider, block_number).await? {
+            Some(header) => Felt::from(header.timestamp),
+            None => return Ok(None),
+        },
+        (None, None) => return Ok(None),
+    };
+    let event_address = event.data.first().copied().unwrap_or(contract.address);
+
+    Ok(Some(vec![event_address, outcome, timestamp]))
+}
+
+async fn from_receipt(
+    ctx: &Context,
+    contract: &Contract,
+    event: &EmittedEvent,
+) -> Result<Option<Vec<Felt>>, ProviderError> {
+    let receipt = ctx
+        .provider
+        .get_transaction_receipt(event.transaction_hash)
+        .await?;
+
+    Ok(receipt_events(&receipt.receipt)
+        .iter()
+        .find(|candidate| candidate.from_address == contract.address && candidate.data.len() >= 3)
+        .map(|candidate| candidate.data.clone()))
+}
+
+fn receipt_events(receipt: &TransactionReceipt) -> &[Event] {
+    match receipt {
+        TransactionReceipt::Invoke(receipt) => &receipt.events,
+        TransactionReceipt::L1Handler(receipt) => &receipt.events,
+        TransactionReceipt::Declare(receipt) => &receipt.events,
+        TransactionReceipt::Deploy(receipt) => &receipt.events,
+        TransactionReceipt::DeployAccount(receipt) => &receipt.events,
+    }
+}
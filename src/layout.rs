@@ -0,0 +1,182 @@
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use starknet::core::types::Felt;
+use std::str::FromStr;
+
+/// How one numeric field is read out of an event's `data`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldDecoder {
+    /// `data[index]` as a plain integer.
+    Direct { index: usize },
+    /// `bits` bits of `data[index]`, starting `shift` bits from the least
+    /// significant end.
+    Packed { index: usize, shift: u32, bits: u32 },
+    /// `count` 128-bit limbs starting at `data[index]`, such as a `u256`
+    /// serialized as `(low, high)` with `little_endian`.
+    Limbs {
+        index: usize,
+        count: usize,
+        little_endian: bool,
+    },
+}
+
+impl FieldDecoder {
+    /// Returns `None` if the field is missing or doesn't fit in a `u64`.
+    pub fn decode(&self, data: &[Felt]) -> Option<u64> {
+        match *self {
+            FieldDecoder::Direct { index } => data.get(index)?.to_u64(),
+            FieldDecoder::Packed { index, shift, bits } => {
+                let mask = (BigUint::from(1u8) << bits) - 1u8;
+                ((data.get(index)?.to_biguint() >> shift) & mask).to_u64()
+            }
+            FieldDecoder::Limbs {
+                index,
+                count,
+                little_endian,
+            } => {
+                let mut limbs = data.get(index..index + count)?.to_vec();
+                if little_endian {
+                    limbs.reverse();
+                }
+                let mut value = BigUint::default();
+                for limb in limbs {
+                    value = (value << 128u32) | BigUint::from(limb.to_u128()?);
+                }
+                value.to_u64()
+            }
+        }
+    }
+
+    /// Number of `data` felts needed to decode the field.
+    fn end(&self) -> usize {
+        match *self {
+            FieldDecoder::Direct { index } | FieldDecoder::Packed { index, .. } => index + 1,
+            FieldDecoder::Limbs { index, count, .. } => index + count,
+        }
+    }
+}
+
+/// Parses `direct:<index>`, `packed:<index>:<shift>:<bits>` or
+/// `limbs:<index>:<count>:<le|be>`.
+impl FromStr for FieldDecoder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = value.trim().split(':').collect();
+        let number = |part: &str| {
+            part.parse::<u32>()
+                .map_err(|_| format!("'{}' is not a number in '{}'", part, value))
+        };
+
+        match parts.as_slice() {
+            ["direct", index] => Ok(FieldDecoder::Direct {
+                index: number(index)? as usize,
+            }),
+            ["packed", index, shift, bits] => {
+                let (shift, bits) = (number(shift)?, number(bits)?);
+                if bits == 0 || bits > 64 || shift + bits > 252 {
+                    return Err(format!("invalid packed subfield in '{}'", value));
+                }
+                Ok(FieldDecoder::Packed {
+                    index: number(index)? as usize,
+                    shift,
+                    bits,
+                })
+            }
+            ["limbs", index, count, order] => {
+                let little_endian = match *order {
+                    "le" => true,
+                    "be" => false,
+                    other => return Err(format!("unknown limb order '{}'", other)),
+                };
+                Ok(FieldDecoder::Limbs {
+                    index: number(index)? as usize,
+                    count: number(count)?.max(1) as usize,
+                    little_endian,
+                })
+            }
+            _ => Err(format!("unknown field decoding '{}'", value)),
+        }
+    }
+}
+
+/// Where EventTimeout's fields live in `data`. The event address is always
+/// `data[0]`; the default reads the outcome and timestamp directly from
+/// `data[1]` and `data[2]`.
+#[derive(Debug, Clone)]
+pub struct EventLayout {
+    pub outcome: FieldDecoder,
+    pub timestamp: FieldDecoder,
+}
+
+impl Default for EventLayout {
+    fn default() -> Self {
+        EventLayout {
+            outcome: FieldDecoder::Direct { index: 1 },
+            timestamp: FieldDecoder::Direct { index: 2 },
+        }
+    }
+}
+
+impl EventLayout {
+    /// Shortest `data` this layout can be parsed from.
+    pub fn min_len(&self) -> usize {
+        self.outcome.end().max(self.timestamp.end()).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout_reads_outcome_and_timestamp_directly() {
+        let layout = EventLayout::default();
+        let data = [
+            Felt::from(0xabcu64),
+            Felt::ONE,
+            Felt::from(1_700_000_000u64),
+        ];
+
+        assert_eq!(layout.outcome.decode(&data), Some(1));
+        assert_eq!(layout.timestamp.decode(&data), Some(1_700_000_000));
+        assert_eq!(layout.min_len(), 3);
+        assert_eq!(layout.timestamp.decode(&data[..2]), None);
+    }
+
+    #[test]
+    fn direct_field_too_large_for_u64_is_rejected() {
+        let data = [Felt::from(u128::MAX)];
+        assert_eq!(FieldDecoder::Direct { index: 0 }.decode(&data), None);
+    }
+
+    #[test]
+    fn packed_subfield_is_masked_and_shifted() {
+        // Outcome 3 in the low 8 bits, timestamp above it.
+        let packed = Felt::from((1_700_000_000u64 << 8) | 3);
+        let outcome: FieldDecoder = "packed:0:0:8".parse().unwrap();
+        let timestamp: FieldDecoder = "packed:0:8:40".parse().unwrap();
+
+        assert_eq!(outcome.decode(&[packed]), Some(3));
+        assert_eq!(timestamp.decode(&[packed]), Some(1_700_000_000));
+    }
+
+    #[test]
+    fn limbs_follow_the_configured_order() {
+        let data = [Felt::from(42u64), Felt::ZERO];
+        let little_endian: FieldDecoder = "limbs:0:2:le".parse().unwrap();
+        let big_endian: FieldDecoder = "limbs:0:2:be".parse().unwrap();
+
+        assert_eq!(little_endian.decode(&data), Some(42));
+        // Read big-endian, 42 is the high limb and overflows a u64.
+        assert_eq!(big_endian.decode(&data), None);
+    }
+
+    #[test]
+    fn invalid_decodings_are_refused() {
+        assert!("packed:0:0:0".parse::<FieldDecoder>().is_err());
+        assert!("packed:0:250:8".parse::<FieldDecoder>().is_err());
+        assert!("limbs:0:2:middle".parse::<FieldDecoder>().is_err());
+        assert!("direct:x".parse::<FieldDecoder>().is_err());
+    }
+}
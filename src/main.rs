@@ -1,22 +1,195 @@
+mod abi;
+mod alerts;
+mod audit;
+mod blocks;
+mod commands;
+mod config;
+mod confirmations;
+mod consistency;
+mod dedup;
+mod digest_log;
+mod drop_guard;
+mod failed_events;
+mod fallback;
+mod layout;
+mod metrics;
+mod orphans;
+mod pages;
+mod quarantine;
+mod reorg;
+mod replay;
+mod retry;
+mod routing;
+mod rpc_batch;
+mod senders;
+mod session;
+mod settings;
+mod shadow;
+mod snapshot;
+mod timeseries;
+mod webhook;
+
+use abi::AbiCache;
+use alerts::Alerter;
+use blocks::HeaderCache;
+use commands::Command;
+use config::{BetMapping, Config, MarketId, Mode, ResolutionModel};
+use confirmations::ConfirmationTracker;
+use consistency::{FetchDigests, RetryConsistency};
+use dedup::ResolvedCache;
+use digest_log::{BlockChanges, DigestLog};
 use dotenv::dotenv;
+use drop_guard::{DropGuard, Verdict};
 use env_logger::Env;
-use log::{error, info};
-use num_traits::ToPrimitive;
-use sqlx::postgres::PgRow;
+use fallback::FallbackCache;
+use layout::EventLayout;
+use log::{debug, error, info, warn};
+use metrics::{Metric, Metrics};
+use quarantine::Quarantine;
+use reorg::RecentResolutions;
+use retry::Backoff;
+use routing::Route;
+use rpc_batch::BatchClient;
+use senders::SenderCache;
+use session::Session;
+use settings::Settings;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnection, PgListener, PgRow};
 use sqlx::Row;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
-use starknet::core::types::{BlockId, EventFilter, Felt};
+use starknet::core::types::{BlockId, EmittedEvent, EventFilter, EventsPage, Felt};
 use starknet::core::utils::get_selector_from_name;
-use starknet::providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider};
+use starknet::providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider, ProviderError};
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::time::Duration;
+use std::future::Future;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use timeseries::{Series, TimeseriesWriter};
 use url::Url;
+use webhook::ResolutionWebhook;
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+/// The first `STRICT_MODE` anomaly of the run, once there is one.
+static STRICT_VIOLATION: OnceLock<String> = OnceLock::new();
+
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const EVENTS_CHUNK_SIZE: u64 = 100;
+const CONFIRM_LARGE_BACKFILL_FLAG: &str = "--confirm-large-backfill";
+/// Print each committed resolution to stdout as NDJSON.
+const EMIT_STDOUT_FLAG: &str = "--emit-stdout";
+const SERIALIZATION_RETRY_DELAY: Duration = Duration::from_millis(10);
+const CATCHUP_STALL_RETRIES: u32 = 4;
+const CATCHUP_STALL_DELAY: Duration = Duration::from_secs(2);
+const REQUIRED_TABLES: [&str; 2] = ["events", "bets"];
+const MAX_TABLE_WAIT_BACKOFF: Duration = Duration::from_secs(60);
+const REPLICATION_LAG_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Why a block couldn't be fully processed; the cursor stops before it.
+#[derive(Debug)]
+enum ProcessError {
+    Rpc(ProviderError),
+    Database(sqlx::Error),
+    /// The RPC returned different events than the previous attempt and the
+    /// block isn't accepted on L1 yet.
+    Inconsistent {
+        block_number: u64,
+    },
+}
+
+impl std::fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcessError::Rpc(e) => write!(f, "RPC error: {}", e),
+            ProcessError::Database(e) => write!(f, "database error: {}", e),
+            ProcessError::Inconsistent { block_number } => write!(
+                f,
+                "RPC returned different events for block {} than the previous attempt",
+                block_number
+            ),
+        }
+    }
+}
+
+impl From<ProviderError> for ProcessError {
+    fn from(e: ProviderError) -> Self {
+        ProcessError::Rpc(e)
+    }
+}
+
+impl From<sqlx::Error> for ProcessError {
+    fn from(e: sqlx::Error) -> Self {
+        ProcessError::Database(e)
+    }
+}
+
+/// An active market contract, with its human-readable label if the
+/// `events` table has one.
+#[derive(Debug, Clone)]
+struct Contract {
+    address: Felt,
+    label: Option<String>,
+    /// Per-contract override of `ALLOWED_OUTCOMES`.
+    allowed_outcomes: Option<Vec<i64>>,
+    /// Per-contract override of `CONFIRMATIONS`.
+    min_confirmations: Option<i64>,
+}
+
+impl Contract {
+    fn confirmations(&self, config: &Config) -> u64 {
+        self.min_confirmations
+            .map_or(config.confirmations, |confirmations| {
+                confirmations.max(0) as u64
+            })
+    }
+
+    fn display_name(&self) -> String {
+        let address = format_address(&self.address.to_hex_string());
+        match &self.label {
+            Some(label) => format!("market: {} ({})", label, address),
+            None => address,
+        }
+    }
+}
 
 #[derive(Debug)]
 struct EventTimeout {
     event_address: String,
     event_outcome: u8,
     timestamp: u64,
+    /// Transaction that emitted the event.
+    transaction_hash: Felt,
+    block_number: Option<u64>,
+    /// Sender of the emitting transaction, with `RESOLVED_BY_COLUMN`.
+    resolved_by: Option<String>,
+    /// Market within the contract, in decimal, with a composite
+    /// `MARKET_IDENTITY`.
+    market_id: Option<String>,
+}
+
+impl EventTimeout {
+    /// Tells resolutions of different markets apart, for caches keyed on
+    /// the market.
+    fn identity(&self) -> String {
+        match &self.market_id {
+            Some(market_id) => format!("{}#{}", self.event_address, market_id),
+            None => self.event_address.clone(),
+        }
+    }
+
+    /// Key for the dedup cache. The outcome and transaction are part of it,
+    /// so after a reorg the canonical resolution isn't mistaken for the
+    /// orphaned one it replaces.
+    fn dedup_key(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            self.identity(),
+            self.event_outcome,
+            self.transaction_hash.to_fixed_hex_string()
+        )
+    }
 }
 
 #[tokio::main]
@@ -25,255 +198,3015 @@ async fn main() {
 
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
 
+    let config = Config::from_env();
+    let (flags, args): (Vec<String>, Vec<String>) = env::args()
+        .skip(1)
+        .partition(|arg| arg == CONFIRM_LARGE_BACKFILL_FLAG || arg == EMIT_STDOUT_FLAG);
+    let command = Command::from_args(args.into_iter());
+
     let rpc_endpoint = env::var("RPC_ENDPOINT").expect("RPC_ENDPOINT must be set");
     let rpc_url = Url::parse(&rpc_endpoint).expect("Invalid RPC URL");
 
+    let batch_client = config.rpc_batch.then(|| {
+        info!(
+            "📦 Batching get_events calls, up to {} per request",
+            config.rpc_batch_size
+        );
+        BatchClient::new(rpc_endpoint.clone(), config.rpc_batch_size)
+    });
+
     let transport = HttpTransport::new(rpc_url);
     let provider = JsonRpcClient::new(transport);
 
-    let pool = setup_database().await;
+    let shadow_provider = config.shadow_rpc_endpoint.as_ref().map(|endpoint| {
+        let shadow_url = Url::parse(endpoint).expect("Invalid SHADOW_RPC_ENDPOINT URL");
+        info!(
+            "🕵️ Shadow mode enabled against {}",
+            shadow_url.host_str().unwrap_or("?")
+        );
+        JsonRpcClient::new(HttpTransport::new(shadow_url))
+    });
+
+    let pool = connect_database(config.db_max_connections).await;
 
-    
-    loop {
-        let contract_addresses = fetch_contract_addresses(&pool).await;
-        process_new_events(&provider, &contract_addresses, &pool).await;
-        tokio::time::sleep(Duration::from_secs(10)).await;
+    if matches!(command, Some(Command::Migrate)) {
+        commands::migrate(&pool).await;
+        return;
     }
-}
 
-async fn setup_database() -> Pool<Postgres> {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    setup_database(&pool, &config).await;
 
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await
-        .expect("Failed to create database pool");
+    let routes = match &config.routing_config {
+        Some(path) => routing::load(path),
+        None => Vec::new(),
+    };
+    routing::validate(&pool, &routes).await;
+    let series = match &config.timeseries_config {
+        Some(path) => timeseries::load(path),
+        None => Vec::new(),
+    };
+    let timeseries_writer = (!series.is_empty()).then(|| {
+        TimeseriesWriter::spawn(
+            config
+                .timeseries_url
+                .clone()
+                .expect("TIMESERIES_URL must be set with TIMESERIES_CONFIG"),
+            config.timeseries_token.clone(),
+            config.timeseries_batch_size,
+        )
+    });
+
+    let control_pool = match config.control_pool_connections {
+        0 => pool.clone(),
+        connections => {
+            info!(
+                "🔒 Reserving {} connection(s) for cursor and control queries",
+                connections
+            );
+            connect_database(connections).await
+        }
+    };
+    let digest_log = match &config.digest_log {
+        Some(sink) => Some(
+            DigestLog::open(sink.clone(), config.digest_algorithm, &control_pool)
+                .await
+                .unwrap_or_else(|e| panic!("Failed to open the digest log: {}", e)),
+        ),
+        None => None,
+    };
 
-    setup_block_state_trunks(&pool).await;
+    // A reindex over inactive contracts is there to re-apply resolved
+    // events, so it must not skip them as duplicates.
+    let reindexing_inactive = matches!(
+        command,
+        Some(Command::ReindexRanges {
+            include_inactive: true,
+            ..
+        })
+    );
+    let ctx = Context {
+        provider,
+        shadow_provider,
+        batch_client,
+        resolved_cache: config
+            .dedup_window
+            .filter(|_| !reindexing_inactive)
+            .map(ResolvedCache::new),
+        headers: HeaderCache::default(),
+        recent_resolutions: RecentResolutions::default(),
+        fetch_digests: FetchDigests::default(),
+        alerter: config
+            .alert_webhook_url
+            .clone()
+            .map(|url| Alerter::new(url, config.alert_min_interval)),
+        resolution_webhook: config.resolution_webhook_url.clone().map(|url| {
+            ResolutionWebhook::spawn(
+                url,
+                config.resolution_webhook_secret.clone(),
+                config.resolution_webhook_queue,
+                config.resolution_webhook_outbox.then(|| pool.clone()),
+            )
+        }),
+        fallback_cache: FallbackCache::default(),
+        senders: SenderCache::default(),
+        abi_cache: config
+            .abi_decoding
+            .then(|| AbiCache::new(config.abi_cache_path.clone())),
+        metrics: Metrics::new(
+            config.metrics_backend,
+            &config.prometheus_addr,
+            &config.statsd_addr,
+            &config.statsd_prefix,
+        ),
+        quarantine: config.quarantine_after_failures.map(Quarantine::new),
+        preloaded_resolved: RwLock::default(),
+        routes,
+        series,
+        timeseries_writer,
+        log_sampling: AtomicBool::new(false),
+        lagging: AtomicBool::new(false),
+        serialization_retries: AtomicU64::new(0),
+        emit_stdout: flags.iter().any(|flag| flag == EMIT_STDOUT_FLAG),
+        confirmations: ConfirmationTracker::default(),
+        session: Session::new(),
+        digest_log,
+        drop_guard: config
+            .contract_drop_threshold
+            .map(|threshold| DropGuard::new(threshold, config.contract_drop_min_previous)),
+        settings: Settings::new(&config),
+        pool,
+        control_pool,
+        config,
+    };
 
-    pool
-}
+    if let Some(command) = command {
+        commands::run(command, &ctx).await;
+        exit_if_strict_violation();
+        return;
+    }
 
-async fn setup_block_state_trunks(pool: &Pool<Postgres>) {
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS block_state_trunks (
-            id INTEGER PRIMARY KEY,
-            last_processed_block BIGINT NOT NULL
-        )",
+    if ctx.config.track_fetch_cursor {
+        reconcile_cursors(&ctx).await;
+    }
+    if ctx.config.check_key_filter {
+        check_key_filter(&ctx).await;
+    }
+    check_startup_lag(
+        &ctx,
+        flags.iter().any(|flag| flag == CONFIRM_LARGE_BACKFILL_FLAG),
     )
-    .execute(pool)
-    .await
-    .expect("Failed to create block_state_trunks table");
+    .await;
 
-    sqlx::query(
-        "INSERT INTO block_state_trunks (id, last_processed_block)
-         VALUES (1, 0)
-         ON CONFLICT (id) DO NOTHING",
-    )
-    .execute(pool)
-    .await
-    .expect("Failed to initialize block_state_trunks");
-}
+    if let Some(path) = &ctx.config.snapshot_path {
+        snapshot::load(&ctx, path, ctx.config.snapshot_max_age).await;
+    }
 
-async fn fetch_contract_addresses(pool: &Pool<Postgres>) -> Vec<Felt> {
-    let contract_addresses: Vec<Felt> =
-        sqlx::query("SELECT address FROM events WHERE is_active = true")
-            .map(|row: PgRow| {
-                let address: String = row.get("address");
-                let felt_address = Felt::from_hex(&address).expect("Invalid Felt");
+    log_event_filters(
+        &ctx,
+        &fetch_contract_addresses(&ctx.control_pool, &ctx.config, false).await,
+    );
 
-                info!(
-                    "Fetched contract address: {} (Felt: {:?})",
-                    address, felt_address
-                );
+    ctx.session.listen_for_shutdown();
+    if ctx.config.replay_on_startup {
+        replay::run(&ctx).await;
+    }
+    match ctx.config.mode {
+        Mode::Follow => {
+            let mut listener = idle_listener(&ctx).await;
+            let mut idle = false;
+            while !ctx.session.shutdown_requested() && !strict_violated() {
+                let Some(contract_addresses) = active_contracts(&ctx).await else {
+                    write_heartbeat(&ctx).await;
+                    tokio::select! {
+                        _ = tokio::time::sleep(FOLLOW_POLL_INTERVAL) => {}
+                        _ = ctx.session.wait_for_shutdown() => {}
+                    }
+                    continue;
+                };
+                process_new_events(&ctx, &contract_addresses).await;
+                write_heartbeat(&ctx).await;
+                if strict_violated() {
+                    break;
+                }
 
-                felt_address
-            })
-            .fetch_all(pool)
-            .await
-            .expect("Failed to fetch contract addresses");
+                let idle_interval = match ctx.config.idle_poll_interval {
+                    Some(interval) if contract_addresses.is_empty() => {
+                        if !idle {
+                            info!(
+                                "💤 No active contracts, polling every {}s until some appear",
+                                interval.as_secs()
+                            );
+                            idle = true;
+                        }
+                        Some(interval)
+                    }
+                    _ => {
+                        if idle {
+                            info!("⏰ Active contracts found, resuming normal polling");
+                            idle = false;
+                        }
+                        None
+                    }
+                };
+                tokio::select! {
+                    _ = wait_for_next_pass(&mut listener, idle_interval) => {}
+                    _ = ctx.session.wait_for_shutdown() => {}
+                }
+            }
+        }
+        Mode::Catchup => run_catchup(&ctx).await,
+    }
 
-    contract_addresses
+    report_session(&ctx).await;
+    exit_if_strict_violation();
 }
 
-async fn process_new_events(
-    provider: &JsonRpcClient<HttpTransport>,
-    contract_addresses: &[Felt],
-    pool: &Pool<Postgres>,
-) {
-    let last_processed_block = get_last_processed_block(pool).await;
-    let latest_block = provider
-        .block_number()
-        .await
-        .expect("Failed to get latest block number");
-
-    info!("Last processed block: {}", last_processed_block);
-    info!("Latest block: {}", latest_block);
+/// Fetches the active contracts and checks the count against the previous
+/// pass. Returns `None` when processing should pause because the count fell
+/// sharply and `PAUSE_ON_CONTRACT_DROP` is set.
+async fn active_contracts(ctx: &Context) -> Option<Vec<Contract>> {
+    let contract_addresses = fetch_contract_addresses(&ctx.control_pool, &ctx.config, false).await;
+    let Some(guard) = &ctx.drop_guard else {
+        return Some(contract_addresses);
+    };
 
-    if latest_block > last_processed_block {
-        info!(
-            "🔀 Processing blocks from {} to {}",
-            last_processed_block + 1,
-            latest_block
-        );
-        for block_number in (last_processed_block + 1)..=latest_block {
-            for &contract_address in contract_addresses {
-                process_block(provider, block_number, contract_address, pool).await;
+    let current = contract_addresses.len();
+    match guard.check(current) {
+        Verdict::Healthy => {}
+        Verdict::Recovered => info!(
+            "✅ Active contract count recovered to {}, resuming",
+            current
+        ),
+        Verdict::Dropped { previous } => {
+            let message = format!(
+                "Active contracts dropped from {} to {} in one pass",
+                previous, current
+            );
+            error!("🚨 {}", message);
+            if let Some(alerter) = &ctx.alerter {
+                alerter.notice("contract_drop", &message);
+            }
+            if ctx.config.pause_on_contract_drop {
+                error!("⏸️ Pausing processing until the count recovers");
+                return None;
+            }
+        }
+        Verdict::StillDropped { previous } => {
+            if ctx.config.pause_on_contract_drop {
+                warn!(
+                    "⏸️ Still paused: {} active contracts, down from {}",
+                    current, previous
+                );
+                return None;
             }
         }
-        update_last_processed_block(pool, latest_block).await;
-    } else {
-        info!("📡 No new blocks to process.");
     }
+    Some(contract_addresses)
 }
 
-async fn get_last_processed_block(pool: &Pool<Postgres>) -> u64 {
-    let row: (i64,) =
-        sqlx::query_as("SELECT last_processed_block FROM block_state_trunks WHERE id = 1")
-            .fetch_one(pool)
-            .await
-            .expect("Failed to fetch last_processed_block");
-
-    row.0 as u64
+async fn wait_for_next_pass(listener: &mut Option<PgListener>, idle_interval: Option<Duration>) {
+    match idle_interval {
+        Some(interval) => wait_while_idle(listener, interval).await,
+        None => tokio::time::sleep(FOLLOW_POLL_INTERVAL).await,
+    }
 }
 
-async fn update_last_processed_block(pool: &Pool<Postgres>, block_number: u64) {
-    if let Err(e) =
-        sqlx::query("UPDATE block_state_trunks SET last_processed_block = $1 WHERE id = 1")
-            .bind(block_number as i64)
-            .execute(pool)
-            .await
-    {
-        error!("Failed to update last_processed_block: {}", e);
+/// Final record of what this instance did, for post-mortems of short runs.
+/// Every exit path goes through here, so it also takes the restart
+/// snapshot.
+async fn report_session(ctx: &Context) {
+    if let Some(path) = &ctx.config.snapshot_path {
+        snapshot::save(ctx, path).await;
     }
+    let final_cursor = get_last_processed_block(&ctx.control_pool).await;
+    ctx.session
+        .report(final_cursor, ctx.config.session_summary_stdout);
 }
 
-async fn process_block(
-    provider: &JsonRpcClient<HttpTransport>,
-    block_number: u64,
-    contract_address: Felt,
-    pool: &Pool<Postgres>,
-) {
-    info!(
-        "Listening for events on contract address: {} (Felt: {:?}) in block {}",
-        format_address(&contract_address.to_hex_string()),
-        contract_address,
-        block_number,
-    );
-
-    let filter = EventFilter {
-        from_block: Some(BlockId::Number(block_number)),
-        to_block: Some(BlockId::Number(block_number)),
-        address: Some(contract_address),
-        keys: Some(vec![vec![event_timeout_event_key()]]),
-    };
+/// Long-lived handles shared by every indexing pass.
+struct Context {
+    provider: JsonRpcClient<HttpTransport>,
+    /// Secondary RPC compared against `provider` in shadow mode. Never used
+    /// for writes.
+    shadow_provider: Option<JsonRpcClient<HttpTransport>>,
+    /// Coalesces each block's first `get_events` page across contracts,
+    /// when `RPC_BATCH` is set.
+    batch_client: Option<BatchClient>,
+    /// Recently resolved event addresses, when deduplication is enabled.
+    resolved_cache: Option<ResolvedCache>,
+    headers: HeaderCache,
+    /// Resolutions from blocks still inside the reorg window.
+    recent_resolutions: RecentResolutions,
+    /// Events seen per block this session, for `RETRY_CONSISTENCY`.
+    fetch_digests: FetchDigests,
+    alerter: Option<Alerter>,
+    senders: SenderCache,
+    /// EventTimeout ABIs by contract, when `ABI_DECODING` is set.
+    abi_cache: Option<AbiCache>,
+    metrics: Metrics,
+    /// Failure counts behind `QUARANTINE_AFTER_FAILURES`, when set.
+    quarantine: Option<Quarantine>,
+    resolution_webhook: Option<ResolutionWebhook>,
+    /// Payloads rebuilt for contracts with `RESOLUTION_FALLBACK_CONTRACTS`.
+    fallback_cache: FallbackCache,
+    /// Event addresses already resolved when the current pass started; only
+    /// populated for long passes with `SKIP_RESOLVED_AT_FETCH`.
+    preloaded_resolved: RwLock<Arc<HashSet<String>>>,
+    /// Config-driven handlers for events other than EventTimeout.
+    routes: Vec<Route>,
+    /// Events also written as time-series points, see `TIMESERIES_CONFIG`.
+    series: Vec<Series>,
+    timeseries_writer: Option<TimeseriesWriter>,
+    /// Set while far behind the tip, see `LOG_SAMPLE_LAG`.
+    log_sampling: AtomicBool,
+    /// Set between a `LAG_ALERT_BLOCKS` alert and its recovery.
+    lagging: AtomicBool,
+    /// Serialization-failure retries so far, for monitoring.
+    serialization_retries: AtomicU64,
+    /// Set by `--emit-stdout`.
+    emit_stdout: bool,
+    /// Contracts processed ahead of the cursor thanks to fewer confirmations.
+    confirmations: ConfirmationTracker,
+    /// Totals reported when the run ends.
+    session: Session,
+    /// Hash chain of processed blocks, when `DIGEST_LOG` is set.
+    digest_log: Option<DigestLog>,
+    /// Watches the active contract count, when `CONTRACT_DROP_THRESHOLD`
+    /// is set.
+    drop_guard: Option<DropGuard>,
+    /// Runtime-tunable settings, refreshed from `indexer_settings`.
+    settings: Settings,
+    /// Used for event writes.
+    pool: Pool<Postgres>,
+    /// Used for the cursor and contract list, so bulk writes can't starve
+    /// them. Shares `pool` unless `CONTROL_POOL_CONNECTIONS` is set.
+    control_pool: Pool<Postgres>,
+    config: Config,
+}
 
-    let chunk_size = 100;
-    let events_page = match provider.get_events(filter, None, chunk_size).await {
-        Ok(page) => page,
-        Err(err) => {
-            error!("Error fetching events: {}", err);
+/// A pass that leaves the cursor where it was failed somewhere, so the next
+/// one waits longer each time and catch-up gives up after a few in a row
+/// rather than spinning through `CATCHUP_MAX_ITERATIONS`.
+async fn run_catchup(ctx: &Context) {
+    let config = &ctx.config;
+    let mut stalls = Backoff::new(CATCHUP_STALL_RETRIES, CATCHUP_STALL_DELAY);
+    for iteration in 1..=config.catchup_max_iterations {
+        if ctx.session.shutdown_requested() {
+            return;
+        }
+        let Some(contract_addresses) = active_contracts(ctx).await else {
+            error!("❌ Active contract count dropped, stopping catch-up for investigation.");
+            report_session(ctx).await;
+            std::process::exit(1);
+        };
+        let cursor = get_last_processed_block(&ctx.control_pool).await;
+        let processed = process_new_events(ctx, &contract_addresses).await;
+        write_heartbeat(ctx).await;
+        if strict_violated() {
+            return;
+        }
+        if !processed {
+            info!("🏁 Caught up to tip after {} pass(es), exiting.", iteration);
             return;
         }
-    };
 
-    info!(
-        "Number of EventTimeout events fetched: {}",
-        events_page.events.len()
+        if get_last_processed_block(&ctx.control_pool).await > cursor {
+            stalls = Backoff::new(CATCHUP_STALL_RETRIES, CATCHUP_STALL_DELAY);
+        } else if !stalls.retry().await {
+            error!(
+                "❌ Cursor stuck at block {} after {} passes without progress, giving up.",
+                cursor,
+                CATCHUP_STALL_RETRIES + 1
+            );
+            report_session(ctx).await;
+            std::process::exit(1);
+        }
+    }
+
+    error!(
+        "❌ Still not caught up after {} passes, giving up.",
+        config.catchup_max_iterations
     );
+    report_session(ctx).await;
+    std::process::exit(1);
+}
 
-    if events_page.events.is_empty() {
-        info!(
-            "No EventTimeout events found for block {} on contract {}",
-            block_number, contract_address
-        );
-    }
+/// Subscribes to `IDLE_NOTIFY_CHANNEL`, if configured, so an idle indexer
+/// wakes up as soon as contracts are added instead of at the next poll.
+async fn idle_listener(ctx: &Context) -> Option<PgListener> {
+    let channel = ctx.config.idle_notify_channel.as_ref()?;
 
-    for event in events_page.events {
-        let data = event.data.clone();
+    let result = async {
+        let mut listener = PgListener::connect_with(&ctx.control_pool).await?;
+        listener.listen(channel).await?;
+        Ok::<_, sqlx::Error>(listener)
+    }
+    .await;
 
-        if let Some(event_finished) = parse_event_finished_event(&data) {
-            info!("✨ New EventFinished event: {:?}", event_finished);
-            update_database_for_event_finished(event_finished, pool).await;
-        } else {
-            error!(
-                "❌ Failed to parse EventFinished event with data: {:?}",
-                data
+    match result {
+        Ok(listener) => {
+            info!("👂 Listening on '{}' for new contracts", channel);
+            Some(listener)
+        }
+        Err(e) => {
+            warn!(
+                "⚠️ Failed to listen on '{}', relying on polling only: {}",
+                channel, e
             );
+            None
         }
     }
 }
 
-fn event_timeout_event_key() -> Felt {
-    let selector =
-        get_selector_from_name("EventTimeout").expect("Failed to compute event selector");
-    info!("EventTimeout selector: {:?}", selector);
-    selector
-}
+/// Sleeps for `interval`, or until a notification arrives on `listener`.
+async fn wait_while_idle(listener: &mut Option<PgListener>, interval: Duration) {
+    let Some(active) = listener else {
+        tokio::time::sleep(interval).await;
+        return;
+    };
 
-fn parse_event_finished_event(data: &[Felt]) -> Option<EventTimeout> {
-    if data.len() >= 3 {
-        let event_address = format_address(&data[0].to_fixed_hex_string());
-        let event_outcome = data[1].to_u8().unwrap_or(0);
-        let timestamp = data[2].to_u64().unwrap_or(0);
+    let notification = tokio::select! {
+        _ = tokio::time::sleep(interval) => return,
+        notification = active.recv() => notification,
+    };
 
-        Some(EventTimeout {
-            event_address,
-            event_outcome,
-            timestamp,
-        })
-    } else {
-        None
+    match notification {
+        Ok(notification) => info!("🔔 Woken up by '{}'", notification.channel()),
+        Err(e) => {
+            warn!("⚠️ Listener failed, falling back to polling: {}", e);
+            *listener = None;
+        }
     }
 }
 
-async fn update_database_for_event_finished(event: EventTimeout, pool: &Pool<Postgres>) {
-    let result =
-        sqlx::query("UPDATE events SET is_active = FALSE, outcome = $1 WHERE address = $2")
-            .bind(event.event_outcome as i32)
-            .bind(&event.event_address)
-            .execute(pool)
-            .await;
+async fn connect_database(max_connections: u32) -> Pool<Postgres> {
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
-    if let Err(e) = result {
-        error!("Failed to update events table: {}", e);
-        return;
-    }
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(&database_url)
+        .await
+        .expect("Failed to create database pool")
+}
 
-    info!(
-        "Updated events table for event_address: {}",
-        event.event_address
-    );
+/// Upserts this instance's heartbeat row so a SQL-only monitor can alert
+/// when `last_loop_at` goes stale.
+async fn write_heartbeat(ctx: &Context) {
+    let Some(instance_name) = &ctx.config.heartbeat_instance else {
+        return;
+    };
 
-    let outcome_as_int = if event.event_outcome == 1 { 1 } else { 0 };
+    let last_processed_block = get_last_processed_block(&ctx.control_pool).await;
     let result = sqlx::query(
-        "UPDATE bets SET is_claimable = TRUE
-        WHERE \"event_address\" = $1 AND bet = $2",
+        "INSERT INTO heartbeat (instance_name, last_loop_at, last_processed_block)
+         VALUES ($1, now(), $2)
+         ON CONFLICT (instance_name) DO UPDATE
+         SET last_loop_at = EXCLUDED.last_loop_at,
+             last_processed_block = EXCLUDED.last_processed_block",
     )
-    .bind(&event.event_address)
-    .bind(outcome_as_int)
-    .execute(pool)
+    .bind(instance_name)
+    .bind(last_processed_block as i64)
+    .execute(&ctx.control_pool)
     .await;
 
     if let Err(e) = result {
-        error!("Failed to update bets table: {}", e);
-    } else {
-        info!(
-            "Updated bets table for event_address: {}",
-            event.event_address
+        error!("Failed to write heartbeat: {}", e);
+    }
+}
+
+/// Runs under a session advisory lock, so when several instances start
+/// against a fresh database exactly one creates the schema and seeds the
+/// cursor while the others wait, then find it done before reading it. Every
+/// step runs on the connection holding the lock, so a small pool can't
+/// deadlock waiting for a second connection.
+async fn setup_database(pool: &Pool<Postgres>, config: &Config) {
+    let mut lock = pool
+        .acquire()
+        .await
+        .expect("Failed to acquire a connection for the setup lock");
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(config.setup_lock_key)
+        .execute(&mut *lock)
+        .await
+        .expect("Failed to take the setup lock");
+    info!("🔒 Holding setup lock {}", config.setup_lock_key);
+
+    run_migrations(&mut lock).await;
+    wait_for_required_tables(&mut lock, config).await;
+    check_events_columns(&mut lock, config).await;
+
+    // The lock is session-level, so it must be released before the
+    // connection goes back to the pool.
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(config.setup_lock_key)
+        .execute(&mut *lock)
+        .await
+        .expect("Failed to release the setup lock");
+}
+
+/// Fails fast if `OUTCOME_COLUMN`, or the soft-resolution columns, don't
+/// exist, rather than on the first resolution.
+async fn check_events_columns(conn: &mut PgConnection, config: &Config) {
+    let mut columns = vec![config.outcome_column.as_str()];
+    if config.resolution_model == ResolutionModel::Soft {
+        columns.extend(["resolved_at", "status"]);
+    }
+    if let Some(column) = &config.resolved_by_column {
+        columns.push(column.as_str());
+    }
+    if let Some(column) = &config.indexed_by_version_column {
+        columns.push(column.as_str());
+    }
+
+    for column in columns {
+        let exists = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (
+                SELECT 1 FROM information_schema.columns
+                WHERE table_name = 'events' AND column_name = $1
+            )",
+        )
+        .bind(column)
+        .fetch_one(&mut *conn)
+        .await
+        .expect("Failed to look up events columns");
+
+        if !exists {
+            panic!("Column '{}' does not exist on the events table", column);
+        }
+    }
+}
+
+async fn wait_for_required_tables(conn: &mut PgConnection, config: &Config) {
+    let started = tokio::time::Instant::now();
+    let mut delay = Duration::from_secs(1);
+
+    loop {
+        let missing = missing_required_tables(conn).await;
+        if missing.is_empty() {
+            info!("✅ Required tables present: {:?}", REQUIRED_TABLES);
+            return;
+        }
+
+        if let Some(timeout) = config.table_wait_timeout {
+            if started.elapsed() >= timeout {
+                panic!(
+                    "Required tables {:?} still missing after {:?}",
+                    missing, timeout
+                );
+            }
+        }
+
+        warn!(
+            "⏳ Waiting for required tables {:?} to be created, retrying in {:?}",
+            missing, delay
         );
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_TABLE_WAIT_BACKOFF);
     }
 }
 
-fn format_address(address: &str) -> String {
-    let hex_str = if address.starts_with("0x") {
-        &address[2..]
-    } else {
-        address
+async fn missing_required_tables(conn: &mut PgConnection) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+
+    for table in REQUIRED_TABLES {
+        let exists = sqlx::query_scalar::<_, bool>("SELECT to_regclass($1) IS NOT NULL")
+            .bind(table)
+            .fetch_one(&mut *conn)
+            .await;
+
+        match exists {
+            Ok(true) => {}
+            Ok(false) => missing.push(table),
+            Err(e) => {
+                error!("Failed to check for table {}: {}", table, e);
+                missing.push(table);
+            }
+        }
+    }
+
+    missing
+}
+
+async fn run_migrations(conn: &mut PgConnection) {
+    MIGRATOR
+        .run(conn)
+        .await
+        .expect("Failed to run database migrations");
+}
+
+/// With `include_inactive`, which only reindex commands ask for, contracts
+/// are returned regardless of whether they are still active.
+async fn fetch_contract_addresses(
+    pool: &Pool<Postgres>,
+    config: &Config,
+    include_inactive: bool,
+) -> Vec<Contract> {
+    let query = contracts_query(config, include_inactive);
+    let contract_addresses: Vec<Contract> = sqlx::query(&query)
+        .map(|row: PgRow| {
+            let address: String = row.get("address");
+            let felt_address = Felt::from_hex(&address).expect("Invalid Felt");
+            let label: Option<String> = row.get("label");
+
+            info!(
+                "Fetched contract address: {} (Felt: {:?}, label: {})",
+                address,
+                felt_address,
+                label.as_deref().unwrap_or("-")
+            );
+
+            Contract {
+                address: felt_address,
+                label,
+                allowed_outcomes: row.get("allowed_outcomes"),
+                min_confirmations: row.get("min_confirmations"),
+            }
+        })
+        .fetch_all(pool)
+        .await
+        .expect("Failed to fetch contract addresses");
+
+    contract_addresses
+}
+
+fn contracts_query(config: &Config, include_inactive: bool) -> String {
+    let label = match &config.event_label_column {
+        Some(column) => format!("{}::TEXT", column),
+        None => "NULL::TEXT".to_string(),
     };
-    let formatted = format!("0x{:0>64}", hex_str);
+    let allowed_outcomes = match (
+        &config.allowed_outcomes_column,
+        &config.outcome_count_column,
+    ) {
+        (Some(column), _) => format!("{}::BIGINT[]", column),
+        (None, Some(column)) => format!(
+            "CASE WHEN {0} IS NULL THEN NULL
+             ELSE ARRAY(SELECT generate_series(0, {0} - 1))::BIGINT[] END",
+            column
+        ),
+        (None, None) => "NULL::BIGINT[]".to_string(),
+    };
+    let min_confirmations = match &config.min_confirmations_column {
+        Some(column) => format!("{}::BIGINT", column),
+        None => "NULL::BIGINT".to_string(),
+    };
+    format!(
+        "SELECT address, {} AS label, {} AS allowed_outcomes, {} AS min_confirmations
+         FROM events WHERE {}",
+        label,
+        allowed_outcomes,
+        min_confirmations,
+        if include_inactive {
+            "TRUE"
+        } else {
+            config.resolution_model.active_filter()
+        }
+    )
+}
 
-    info!("Formatted address: {}", formatted);
-    formatted
+/// Processes every block between the stored cursor and the tip.
+/// Returns `false` when there was nothing new to process.
+async fn process_new_events(ctx: &Context, contract_addresses: &[Contract]) -> bool {
+    let pool = &ctx.control_pool;
+    ctx.settings.refresh_if_due(pool).await;
+    wait_for_replicas(ctx).await;
+
+    let mut fork_point = None;
+    if ctx.config.recent_blocks_depth > 0 {
+        fork_point = reorg::detect_fork(ctx).await;
+    }
+    if fork_point.is_none() && ctx.config.content_hash_window > 0 {
+        fork_point = reorg::detect_content_change(ctx).await;
+    }
+    if let Some(fork_point) = fork_point {
+        // If the rewind guard refuses, the window is kept so the fork is
+        // reported again on the next pass.
+        if update_last_processed_block(ctx, fork_point).await {
+            if let Err(e) = reorg::rollback(ctx, fork_point).await {
+                error!("Failed to roll back to block {}: {}", fork_point, e);
+            }
+            ctx.confirmations.reset();
+            if ctx.config.track_fetch_cursor {
+                update_last_fetched_block(pool, fork_point).await;
+            }
+        }
+    }
+
+    let last_processed_block = get_last_processed_block(pool).await;
+    let latest_block = ctx
+        .provider
+        .block_number()
+        .await
+        .expect("Failed to get latest block number");
+
+    info!("Last processed block: {}", last_processed_block);
+    info!("Latest block: {}", latest_block);
+    update_log_sampling(ctx, latest_block.saturating_sub(last_processed_block));
+    ctx.metrics.gauge(
+        Metric::Lag,
+        latest_block.saturating_sub(last_processed_block),
+    );
+
+    // Contracts needing fewer confirmations are processed up to their own
+    // depth, but the cursor only advances as far as every contract allows.
+    let depths = contract_addresses
+        .iter()
+        .map(|contract| contract.confirmations(&ctx.config));
+    let fewest = depths.clone().min().unwrap_or(ctx.config.confirmations);
+    let most = depths.max().unwrap_or(ctx.config.confirmations);
+    let process_to = latest_block.saturating_sub(fewest);
+    let cursor_limit = latest_block.saturating_sub(most);
+    ctx.confirmations.set_tip(latest_block);
+
+    if process_to > last_processed_block {
+        info!(
+            "🔀 Processing blocks from {} to {}",
+            last_processed_block + 1,
+            process_to
+        );
+        // The preloaded set is keyed on the event address alone, which would
+        // skip every market of a contract once one is resolved.
+        if ctx.config.skip_resolved_at_fetch
+            && ctx.config.market_identity.market_id().is_none()
+            && process_to - last_processed_block >= ctx.config.skip_resolved_min_blocks
+        {
+            preload_resolved_addresses(ctx).await;
+        }
+
+        let started = Instant::now();
+        let committed_block = process_block_range(
+            ctx,
+            contract_addresses,
+            last_processed_block + 1,
+            process_to,
+            true,
+        )
+        .await;
+        *ctx.preloaded_resolved.write().unwrap() = Arc::default();
+        let cursor = committed_block.min(cursor_limit).max(last_processed_block);
+        if cursor > last_processed_block {
+            update_last_processed_block(ctx, cursor).await;
+            ctx.metrics
+                .count(Metric::BlocksProcessed, cursor - last_processed_block);
+            ctx.session.add_blocks(cursor - last_processed_block);
+        }
+        for contract in contract_addresses {
+            let reached = latest_block.saturating_sub(contract.confirmations(&ctx.config));
+            ctx.confirmations
+                .processed(contract.address, committed_block.min(reached), cursor);
+        }
+        ctx.confirmations.prune(cursor);
+        retry_quarantined(ctx, contract_addresses, cursor).await;
+        if ctx.config.buffer_orphan_resolutions {
+            orphans::reapply(ctx).await;
+        }
+        if let Some(lag) = ctx.config.audit_lag_blocks {
+            audit::run(ctx, lag).await;
+        }
+        if ctx.config.index_block_metadata && ctx.config.track_l1_acceptance {
+            blocks::backfill_l1_acceptance(&ctx.provider, &ctx.pool).await;
+        }
+        ctx.metrics.timing(Metric::PassDuration, started.elapsed());
+        update_lag_alert(ctx, latest_block, cursor);
+        cursor_limit > last_processed_block
+    } else {
+        info!("📡 No new blocks to process.");
+        update_lag_alert(ctx, latest_block, last_processed_block);
+        retry_quarantined(ctx, contract_addresses, last_processed_block).await;
+        if ctx.config.buffer_orphan_resolutions {
+            orphans::reapply(ctx).await;
+        }
+        false
+    }
+}
+
+/// Catches each quarantined contract up on its own, block by block, to the
+/// global cursor, and returns it to the main pass once it gets there.
+async fn retry_quarantined(ctx: &Context, contract_addresses: &[Contract], cursor: u64) {
+    if ctx.quarantine.is_none() {
+        return;
+    }
+    let held = match quarantine::held(&ctx.control_pool).await {
+        Ok(held) => held,
+        Err(e) => {
+            error!("Failed to load quarantined contracts: {}", e);
+            return;
+        }
+    };
+
+    for (address, from_block) in held {
+        let Some(contract) = contract_addresses.iter().find(|c| c.address == address) else {
+            info!(
+                "Quarantined contract {} is no longer active, releasing it",
+                format_address(&address.to_hex_string())
+            );
+            if let Err(e) = quarantine::release(&ctx.control_pool, address).await {
+                error!("Failed to release quarantined contract: {}", e);
+            }
+            continue;
+        };
+
+        let mut next_block = from_block;
+        while next_block <= cursor {
+            let events = match process_block(ctx, next_block, contract, None).await {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!(
+                        "⚠️ Quarantined {} still failing at block {}: {}",
+                        contract.display_name(),
+                        next_block,
+                        e
+                    );
+                    break;
+                }
+            };
+            let mut batch = ResolutionBatch::default();
+            for event in skip_resolved(ctx, events).await {
+                batch.push(next_block, event);
+            }
+            if batch.commit_with_retries(ctx).await.is_err() {
+                break;
+            }
+            next_block += 1;
+        }
+
+        let result = if next_block > cursor {
+            info!(
+                "✅ {} caught up to block {}, releasing it from quarantine",
+                contract.display_name(),
+                cursor
+            );
+            quarantine::release(&ctx.control_pool, address).await
+        } else if next_block > from_block {
+            quarantine::advance(&ctx.control_pool, address, next_block).await
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            error!("Failed to update quarantined contract: {}", e);
+        }
+    }
+}
+
+/// Counts a live failure of `contract` on `block_number` and, once it
+/// reaches `QUARANTINE_AFTER_FAILURES`, quarantines the contract so the
+/// block can advance without it. Returns whether it was quarantined.
+async fn quarantine_contract(
+    ctx: &Context,
+    contract: &Contract,
+    block_number: u64,
+    error: &ProcessError,
+) -> bool {
+    let Some(quarantine) = &ctx.quarantine else {
+        return false;
+    };
+    if !quarantine.failed(contract.address, block_number) {
+        return false;
+    }
+
+    let reason = error.to_string();
+    if let Err(e) =
+        quarantine::hold(&ctx.control_pool, contract.address, block_number, &reason).await
+    {
+        error!("Failed to quarantine {}: {}", contract.display_name(), e);
+        return false;
+    }
+
+    let message = format!(
+        "{} failed block {} {} times and is quarantined: {}",
+        contract.display_name(),
+        block_number,
+        ctx.config.quarantine_after_failures.unwrap_or_default(),
+        reason
+    );
+    error!("🚧 {}", message);
+    ctx.metrics.count(Metric::ContractsQuarantined, 1);
+    if let Some(alerter) = &ctx.alerter {
+        alerter.notice("quarantine", &message);
+    }
+    true
+}
+
+/// Holds the pass while any replica's replay lag, as reported by
+/// `pg_stat_replication` on the primary, exceeds `MAX_REPLICATION_LAG_SECS`,
+/// so writes don't widen the gap readers see.
+async fn wait_for_replicas(ctx: &Context) {
+    let Some(max_lag) = ctx.config.max_replication_lag else {
+        return;
+    };
+
+    let mut paused = false;
+    loop {
+        let lag = sqlx::query_scalar::<_, f64>(
+            "SELECT COALESCE(MAX(EXTRACT(EPOCH FROM replay_lag))::FLOAT8, 0)
+             FROM pg_stat_replication",
+        )
+        .fetch_one(&ctx.control_pool)
+        .await;
+
+        let lag = match lag {
+            Ok(lag) => Duration::from_secs_f64(lag.max(0.0)),
+            Err(e) => {
+                warn!("⚠️ Failed to check replication lag, not pausing: {}", e);
+                return;
+            }
+        };
+
+        if lag <= max_lag {
+            if paused {
+                info!("✅ Replication lag back to {:?}, resuming writes", lag);
+            }
+            return;
+        }
+
+        if !paused {
+            let message = format!(
+                "Replication lag {:?} exceeds {:?}, pausing writes",
+                lag, max_lag
+            );
+            error!("🚧 {}", message);
+            if let Some(alerter) = &ctx.alerter {
+                alerter.notice("replication_lag", &message);
+            }
+            paused = true;
+        }
+        tokio::time::sleep(REPLICATION_LAG_POLL_INTERVAL).await;
+    }
+}
+
+/// Turns log sampling on while the indexer is more than `LOG_SAMPLE_LAG`
+/// blocks behind, and back off once it catches up.
+fn update_log_sampling(ctx: &Context, lag: u64) {
+    let Some(threshold) = ctx.config.log_sample_lag else {
+        return;
+    };
+
+    let sampling = lag > threshold;
+    let was_sampling = ctx.log_sampling.swap(sampling, Ordering::Relaxed);
+    if sampling && !was_sampling {
+        info!(
+            "🤫 {} blocks behind, logging every {}th block until caught up",
+            lag, ctx.config.log_sample_every
+        );
+    } else if !sampling && was_sampling {
+        info!("🔊 Caught up, restoring full logging");
+    }
+}
+
+/// Alerts once when the cursor falls more than `LAG_ALERT_BLOCKS` behind the
+/// tip, and once more when it is back within `LAG_CLEAR_BLOCKS`, which
+/// defaults to half the alert threshold so a cursor hovering around the
+/// threshold doesn't flap.
+fn update_lag_alert(ctx: &Context, latest_block: u64, cursor: u64) {
+    let Some(threshold) = ctx.config.lag_alert_blocks else {
+        return;
+    };
+    let clear_below = ctx.config.lag_clear_blocks.unwrap_or(threshold / 2);
+
+    let lag = latest_block.saturating_sub(cursor);
+    let was_lagging = ctx.lagging.load(Ordering::Relaxed);
+    if !was_lagging && lag > threshold {
+        ctx.lagging.store(true, Ordering::Relaxed);
+        let message = format!(
+            "Lagging {} blocks behind the tip {}, at block {}",
+            lag, latest_block, cursor
+        );
+        warn!("🐢 {}", message);
+        if let Some(alerter) = &ctx.alerter {
+            alerter.transition("lag", &message, cursor);
+        }
+    } else if was_lagging && lag <= clear_below {
+        ctx.lagging.store(false, Ordering::Relaxed);
+        let message = format!("Lag cleared, caught up to block {}", cursor);
+        info!("✅ {}", message);
+        if let Some(alerter) = &ctx.alerter {
+            alerter.transition("lag_cleared", &message, cursor);
+        }
+    }
+}
+
+/// Whether per-block INFO lines are logged for `block_number`. Errors are
+/// always logged.
+fn logs_block(ctx: &Context, block_number: u64) -> bool {
+    !ctx.log_sampling.load(Ordering::Relaxed) || block_number % ctx.config.log_sample_every == 0
+}
+
+/// Indexes `from..=to` and returns the last block whose resolutions are all
+/// committed. Writes are idempotent, so a crash between a commit and the
+/// cursor update only replays resolutions that were already applied.
+///
+/// `live` passes follow the tip and also maintain the reorg window and fetch
+/// cursor; backfills of older ranges leave both alone.
+async fn process_block_range(
+    ctx: &Context,
+    contract_addresses: &[Contract],
+    from: u64,
+    to: u64,
+    live: bool,
+) -> u64 {
+    let mut processed = Vec::new();
+    let committed_block =
+        process_blocks(ctx, contract_addresses, from, to, live, &mut processed).await;
+    if let Some(digest_log) = &ctx.digest_log {
+        processed.retain(|block: &BlockChanges| block.block_number <= committed_block);
+        digest_log.append(ctx, &processed).await;
+    }
+    committed_block
+}
+
+/// Does the work of `process_block_range`, adding what was applied for
+/// each fully handled block to `processed`.
+async fn process_blocks(
+    ctx: &Context,
+    contract_addresses: &[Contract],
+    from: u64,
+    to: u64,
+    live: bool,
+    processed: &mut Vec<BlockChanges>,
+) -> u64 {
+    let config = &ctx.config;
+    let mut batch = ResolutionBatch::default();
+    let mut committed_block = from - 1;
+    // Quarantined contracts are caught up separately by
+    // `retry_quarantined`, so the live pass leaves them out.
+    let mut quarantined: HashSet<Felt> = HashSet::new();
+    if live && ctx.quarantine.is_some() {
+        match quarantine::held(&ctx.control_pool).await {
+            Ok(held) => quarantined.extend(held.into_iter().map(|(address, _)| address)),
+            Err(e) => {
+                error!("Failed to load quarantined contracts: {}", e);
+                return committed_block;
+            }
+        }
+    }
+
+    let started = Instant::now();
+    let mut last_block = to;
+    for block_number in from..=to {
+        // Checked between blocks only, so a pass always makes progress and
+        // never stops partway through a block.
+        if live && block_number > from {
+            if ctx.session.shutdown_requested() {
+                info!(
+                    "🛑 Shutdown requested, ending the pass before block {}",
+                    block_number
+                );
+                last_block = block_number - 1;
+                break;
+            }
+            if matches!(config.pass_time_budget, Some(budget) if started.elapsed() >= budget) {
+                info!(
+                    "⏱️ Pass time budget used up, yielding before block {}",
+                    block_number
+                );
+                last_block = block_number - 1;
+                break;
+            }
+        }
+        if ctx.log_sampling.load(Ordering::Relaxed) && logs_block(ctx, block_number) {
+            info!(
+                "📈 Block {} of {}..={} ({:.1}%)",
+                block_number,
+                from,
+                to,
+                (block_number - from + 1) as f64 * 100.0 / (to - from + 1) as f64
+            );
+        }
+        let mut block_event_count = 0;
+        let mut block_changes = BlockChanges::new(block_number);
+        let mut block_events = Vec::new();
+        let mut first_pages = prefetch_first_pages(ctx, contract_addresses, block_number).await;
+
+        for contract in contract_addresses {
+            let first_page = first_pages.as_mut().and_then(|pages| pages.next());
+            if quarantined.contains(&contract.address) {
+                continue;
+            }
+            if live
+                && !ctx.confirmations.due(
+                    contract.address,
+                    contract.confirmations(config),
+                    block_number,
+                )
+            {
+                continue;
+            }
+            let events = match process_block(ctx, block_number, contract, first_page).await {
+                Ok(events) => events,
+                Err(e)
+                    if live
+                        && !strict_violated()
+                        && quarantine_contract(ctx, contract, block_number, &e).await =>
+                {
+                    quarantined.insert(contract.address);
+                    continue;
+                }
+                Err(e) => {
+                    ctx.session.add_error();
+                    error!(
+                        "❌ Stopping before block {}, {} failed: {}",
+                        block_number,
+                        contract.display_name(),
+                        e
+                    );
+                    // Everything before this block is fully fetched, so it
+                    // can still be committed and skipped on the next pass.
+                    // Nothing from this block is in the batch yet, so it is
+                    // retried as a whole.
+                    if batch.commit_with_retries(ctx).await.is_ok() {
+                        committed_block = block_number - 1;
+                    }
+                    return committed_block;
+                }
+            };
+
+            block_event_count += events.len();
+            block_events.extend(events);
+        }
+
+        // Only once every contract's pages for the block are in does any of
+        // it reach the batch, so a failed fetch never leaves part of a block
+        // committed, whatever the batch size. With `TX_ATOMIC_COMMIT`,
+        // batches are only cut between transactions, so each transaction's
+        // resolutions land in a single DB transaction.
+        let block_events = skip_resolved(ctx, block_events).await;
+        let transactions = if config.tx_atomic_commit {
+            group_by_transaction(block_events)
+        } else {
+            block_events.into_iter().map(|event| vec![event]).collect()
+        };
+        for transaction in transactions {
+            for event in transaction {
+                block_changes.push(&event);
+                batch.push(block_number, event);
+            }
+            if batch.events.len() >= ctx.settings.batch_commit_size()
+                && batch.commit_with_retries(ctx).await.is_err()
+            {
+                return committed_block;
+            }
+        }
+
+        if config.index_block_metadata {
+            record_block_metadata(ctx, block_number, block_event_count).await;
+        }
+
+        if live && config.recent_blocks_depth > 0 && !record_recent_block(ctx, block_number).await {
+            // The chain moved under us; the next pass detects the fork and
+            // rolls back. Blocks before this one are still safe to commit.
+            if batch.commit_with_retries(ctx).await.is_ok() {
+                committed_block = block_number - 1;
+            }
+            return committed_block;
+        }
+
+        if live && config.content_hash_window > 0 && !config.streams_pages() {
+            let digests: Vec<(Felt, u64)> = contract_addresses
+                .iter()
+                .filter_map(|contract| {
+                    let digest = ctx.fetch_digests.get(contract.address, block_number)?;
+                    Some((contract.address, digest))
+                })
+                .collect();
+            if let Err(e) = reorg::record_contents(ctx, block_number, &digests).await {
+                error!("Failed to record block {} contents: {}", block_number, e);
+            }
+        }
+
+        if live && config.track_fetch_cursor {
+            update_last_fetched_block(&ctx.control_pool, block_number).await;
+        }
+
+        if batch.spans_window(block_number, ctx.settings.batch_commit_blocks())
+            && batch.commit_with_retries(ctx).await.is_err()
+        {
+            return committed_block;
+        }
+        if batch.events.is_empty() {
+            committed_block = block_number;
+        }
+        block_changes.event_count = block_event_count;
+        processed.push(block_changes);
+    }
+
+    if batch.commit_with_retries(ctx).await.is_err() {
+        return committed_block;
+    }
+    last_block
+}
+
+/// Groups events by emitting transaction, in order of first appearance and
+/// keeping each transaction's events in their original order.
+fn group_by_transaction(events: Vec<EventTimeout>) -> Vec<Vec<EventTimeout>> {
+    let mut transactions: Vec<Vec<EventTimeout>> = Vec::new();
+    for event in events {
+        match transactions
+            .iter_mut()
+            .find(|transaction| transaction[0].transaction_hash == event.transaction_hash)
+        {
+            Some(transaction) => transaction.push(event),
+            None => transactions.push(vec![event]),
+        }
+    }
+    transactions
+}
+
+/// Adds `block_number` to the reorg window. Returns `false` when the block
+/// can't be recorded or no longer builds on the stored previous block.
+async fn record_recent_block(ctx: &Context, block_number: u64) -> bool {
+    let header = match ctx.headers.get(&ctx.provider, block_number).await {
+        Ok(Some(header)) => header,
+        Ok(None) => return false,
+        Err(e) => {
+            error!("Failed to fetch header for block {}: {}", block_number, e);
+            return false;
+        }
+    };
+
+    match reorg::record(ctx, &header).await {
+        Ok(canonical) => canonical,
+        Err(e) => {
+            error!("Failed to record block {} hash: {}", block_number, e);
+            false
+        }
+    }
+}
+
+async fn record_block_metadata(ctx: &Context, block_number: u64, event_count: usize) {
+    match ctx.headers.get(&ctx.provider, block_number).await {
+        Ok(Some(header)) => blocks::upsert_block(&ctx.pool, &header, event_count).await,
+        Ok(None) => warn!("Block {} is still pending, skipping metadata", block_number),
+        Err(e) => error!("Failed to fetch header for block {}: {}", block_number, e),
+    }
+}
+
+/// Resolutions waiting to be applied together in a single transaction.
+#[derive(Default)]
+struct ResolutionBatch {
+    events: Vec<EventTimeout>,
+    first_block: Option<u64>,
+}
+
+impl ResolutionBatch {
+    fn push(&mut self, block_number: u64, event: EventTimeout) {
+        self.first_block.get_or_insert(block_number);
+        self.events.push(event);
+    }
+
+    fn spans_window(&self, block_number: u64, window: Option<u64>) -> bool {
+        match (window, self.first_block) {
+            (Some(window), Some(first_block)) => block_number - first_block + 1 >= window,
+            _ => false,
+        }
+    }
+
+    /// Retries a failed commit up to `IN_PASS_RETRIES` times so transient
+    /// failures heal without waiting for the next poll.
+    async fn commit_with_retries(&mut self, ctx: &Context) -> Result<(), sqlx::Error> {
+        let mut backoff = ctx.settings.in_pass_backoff();
+        loop {
+            match self.commit(ctx).await {
+                Err(_) if !strict_violated() && backoff.retry().await => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// Applies the batch in one transaction. On failure the batch is kept so
+    /// it can be retried.
+    async fn commit(&mut self, ctx: &Context) -> Result<(), sqlx::Error> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+
+        let events = &self.events;
+        let result = retry_serialization_failures(ctx, || async move {
+            let mut tx = ctx.pool.begin().await?;
+            let mut written = Vec::with_capacity(events.len());
+            for event in events {
+                written
+                    .push(update_database_for_event_finished(event, &mut tx, &ctx.config).await?);
+            }
+            tx.commit().await?;
+            Ok(written)
+        })
+        .await;
+
+        let written = match result {
+            Ok(written) => written,
+            Err(e) => {
+                ctx.session.add_error();
+                error!(
+                    "Failed to commit batch of {} resolution(s): {}",
+                    self.events.len(),
+                    e
+                );
+                return Err(e);
+            }
+        };
+
+        info!("💾 Committed {} resolution(s)", self.events.len());
+        after_commit(ctx, &applied(std::mem::take(&mut self.events), &written));
+        self.first_block = None;
+        Ok(())
+    }
+}
+
+/// The events whose resolution was applied rather than buffered.
+fn applied(events: Vec<EventTimeout>, written: &[Written]) -> Vec<EventTimeout> {
+    events
+        .into_iter()
+        .zip(written)
+        .filter(|(_, written)| **written == Written::Applied)
+        .map(|(event, _)| event)
+        .collect()
+}
+
+/// Bookkeeping for resolutions that are now durable.
+fn after_commit(ctx: &Context, events: &[EventTimeout]) {
+    ctx.metrics
+        .count(Metric::ResolutionsCommitted, events.len() as u64);
+    ctx.session.add_events(events.len() as u64);
+    let reorg_window = ctx
+        .config
+        .recent_blocks_depth
+        .max(ctx.config.content_hash_window);
+    for event in events {
+        if reorg_window > 0 {
+            ctx.recent_resolutions.record(event, reorg_window);
+        }
+        if let Some(cache) = &ctx.resolved_cache {
+            cache.insert(&event.dedup_key());
+        }
+        if let Some(webhook) = &ctx.resolution_webhook {
+            webhook.notify(event);
+        }
+    }
+    if ctx.emit_stdout {
+        emit_stdout(events);
+    }
+}
+
+/// Writes one JSON object per line, the same payload as the resolution
+/// webhook, and flushes after each batch so pipeline consumers see it at
+/// once. Logs go to stderr, so the two streams never interleave.
+fn emit_stdout(events: &[EventTimeout]) {
+    let mut stdout = io::stdout().lock();
+    let result = events
+        .iter()
+        .try_for_each(|event| writeln!(stdout, "{}", webhook::payload(event)))
+        .and_then(|()| stdout.flush());
+    if let Err(e) = result {
+        error!("Failed to write resolutions to stdout: {}", e);
+    }
+}
+
+/// Reruns a write transaction that failed with a serialization failure or
+/// deadlock (SQLSTATE 40001 / 40P01). These are expected under concurrency,
+/// so they're retried quickly with jitter, separately from `IN_PASS_RETRIES`.
+async fn retry_serialization_failures<T, F, Fut>(
+    ctx: &Context,
+    mut attempt: F,
+) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut retries = 0;
+    let mut delay = SERIALIZATION_RETRY_DELAY;
+    loop {
+        match attempt().await {
+            Err(e)
+                if retry::is_serialization_failure(&e)
+                    && retries < ctx.config.serialization_retries =>
+            {
+                retries += 1;
+                let total = ctx.serialization_retries.fetch_add(1, Ordering::Relaxed) + 1;
+                ctx.metrics.count(Metric::SerializationRetries, 1);
+                warn!(
+                    "🔁 Serialization failure ({}), retry {}/{} ({} this session)",
+                    e, retries, ctx.config.serialization_retries, total
+                );
+                tokio::time::sleep(retry::jittered(delay)).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn skip_resolved(ctx: &Context, events: Vec<EventTimeout>) -> Vec<EventTimeout> {
+    let mut fresh = Vec::with_capacity(events.len());
+    for event in events {
+        if is_already_resolved(ctx, &event).await {
+            debug!(
+                "Skipping already-resolved event_address {}",
+                event.event_address
+            );
+        } else {
+            fresh.push(event);
+        }
+    }
+    fresh
+}
+
+/// Loads every already-resolved event address so a long reindex pass can
+/// skip parsing and applying their events.
+async fn preload_resolved_addresses(ctx: &Context) {
+    let addresses = sqlx::query_scalar::<_, String>(&format!(
+        "SELECT address FROM events WHERE {}",
+        ctx.config.resolution_model.resolved_filter()
+    ))
+    .fetch_all(&ctx.control_pool)
+    .await;
+
+    match addresses {
+        Ok(addresses) => {
+            info!(
+                "⏭️ Skipping {} already-resolved event address(es) this pass",
+                addresses.len()
+            );
+            *ctx.preloaded_resolved.write().unwrap() = Arc::new(addresses.into_iter().collect());
+        }
+        Err(e) => error!("Failed to load resolved event addresses: {}", e),
+    }
+}
+
+/// With deduplication enabled, reports whether `event` was applied recently
+/// or its row is already resolved with the same outcome, in which case
+/// re-applying it is wasted work. A row resolved with another outcome, for
+/// example by a resolution a reorg orphaned, is not skipped.
+async fn is_already_resolved(ctx: &Context, event: &EventTimeout) -> bool {
+    let Some(cache) = &ctx.resolved_cache else {
+        return false;
+    };
+    if cache.contains(&event.dedup_key()) {
+        return true;
+    }
+
+    let query = format!(
+        "SELECT COALESCE(({}) AND {} = $2::{}, FALSE) FROM events WHERE address = $1{}",
+        ctx.config.resolution_model.resolved_filter(),
+        ctx.config.outcome_column,
+        ctx.config.outcome_column_type,
+        ctx.config
+            .market_filter(|market_id| market_id.events_column.as_str(), 3)
+    );
+    let mut resolved = sqlx::query_scalar::<_, bool>(&query)
+        .bind(&event.event_address)
+        .bind(event.event_outcome as i32);
+    if ctx.config.market_identity.market_id().is_some() {
+        resolved = resolved.bind(&event.market_id);
+    }
+
+    match resolved.fetch_optional(&ctx.pool).await {
+        Ok(Some(true)) => {
+            cache.insert(&event.dedup_key());
+            true
+        }
+        Ok(_) => false,
+        Err(e) => {
+            error!("Failed to check resolution state: {}", e);
+            false
+        }
+    }
+}
+
+async fn get_last_processed_block(pool: &Pool<Postgres>) -> u64 {
+    let row: (i64,) =
+        sqlx::query_as("SELECT last_processed_block FROM block_state_trunks WHERE id = 1")
+            .fetch_one(pool)
+            .await
+            .expect("Failed to fetch last_processed_block");
+
+    row.0 as u64
+}
+
+/// Moves the cursor, refusing rewinds of more than `MAX_CURSOR_REWIND`
+/// blocks unless `ALLOW_CURSOR_REWIND` is set, since those trigger a costly
+/// reprocess. Returns whether the cursor was moved.
+async fn update_last_processed_block(ctx: &Context, block_number: u64) -> bool {
+    let pool = &ctx.control_pool;
+    let current = get_last_processed_block(pool).await;
+    let rewind = current.saturating_sub(block_number);
+    if rewind > ctx.config.max_cursor_rewind {
+        if !ctx.config.allow_cursor_rewind {
+            error!(
+                "🛑 Refusing to move cursor back {} blocks ({} -> {}), more than MAX_CURSOR_REWIND={}. Set ALLOW_CURSOR_REWIND=true to force it.",
+                rewind, current, block_number, ctx.config.max_cursor_rewind
+            );
+            return false;
+        }
+        warn!(
+            "⚠️⚠️ Forcing cursor back {} blocks ({} -> {}) because ALLOW_CURSOR_REWIND is set",
+            rewind, current, block_number
+        );
+    }
+
+    if let Err(e) =
+        sqlx::query("UPDATE block_state_trunks SET last_processed_block = $1 WHERE id = 1")
+            .bind(block_number as i64)
+            .execute(pool)
+            .await
+    {
+        error!("Failed to update last_processed_block: {}", e);
+        return false;
+    }
+    true
+}
+
+async fn update_last_fetched_block(pool: &Pool<Postgres>, block_number: u64) {
+    if let Err(e) =
+        sqlx::query("UPDATE block_state_trunks SET last_fetched_block = $1 WHERE id = 1")
+            .bind(block_number as i64)
+            .execute(pool)
+            .await
+    {
+        error!("Failed to update last_fetched_block: {}", e);
+    }
+}
+
+/// Recent blocks scanned for a sample event by `check_key_filter`.
+const KEY_FILTER_CHECK_BLOCKS: u64 = 10;
+
+/// Self-check for providers that ignore or misread the positional `keys`
+/// filter: takes the first key of a sample event from a recent block, asks
+/// for that block's events filtered by it, and checks that everything
+/// returned carries it and that the sample is among the results.
+async fn check_key_filter(ctx: &Context) {
+    let latest_block = match ctx.provider.block_number().await {
+        Ok(latest_block) => latest_block,
+        Err(e) => {
+            warn!("⚠️ Skipping the key filter check: {}", e);
+            return;
+        }
+    };
+
+    for block_number in (latest_block.saturating_sub(KEY_FILTER_CHECK_BLOCKS)..=latest_block).rev()
+    {
+        let block = Some(BlockId::Number(block_number));
+        let unfiltered = EventFilter {
+            from_block: block,
+            to_block: block,
+            address: None,
+            keys: None,
+        };
+        let sample = match ctx.provider.get_events(unfiltered, None, 1).await {
+            Ok(page) => page.events.into_iter().next(),
+            Err(e) => {
+                warn!("⚠️ Skipping the key filter check: {}", e);
+                return;
+            }
+        };
+        let Some(sample) = sample else {
+            continue;
+        };
+        let Some(key) = sample.keys.first().copied() else {
+            continue;
+        };
+
+        let filtered = EventFilter {
+            from_block: block,
+            to_block: block,
+            address: None,
+            keys: Some(vec![vec![key]]),
+        };
+        let events = match ctx
+            .provider
+            .get_events(filtered, None, EVENTS_CHUNK_SIZE)
+            .await
+        {
+            Ok(page) => page.events,
+            Err(e) => {
+                warn!("⚠️ Skipping the key filter check: {}", e);
+                return;
+            }
+        };
+
+        let unmatched = events
+            .iter()
+            .filter(|event| event.keys.first() != Some(&key))
+            .count();
+        let problem = if unmatched > 0 {
+            Some(format!(
+                "RPC ignores the keys filter: {} of {} events in block {} don't have key {}",
+                unmatched,
+                events.len(),
+                block_number,
+                key.to_fixed_hex_string()
+            ))
+        } else if !events
+            .iter()
+            .any(|event| event.transaction_hash == sample.transaction_hash)
+        {
+            Some(format!(
+                "RPC misreads the keys filter: filtering block {} by key {} dropped an event that has it",
+                block_number,
+                key.to_fixed_hex_string()
+            ))
+        } else {
+            None
+        };
+
+        match problem {
+            Some(message) => {
+                error!("🚨 {}. Events would be over-fetched or missed.", message);
+                if let Some(alerter) = &ctx.alerter {
+                    alerter.notice("key_filter_check", &message);
+                }
+            }
+            None => info!(
+                "✅ RPC honours the keys filter (checked on block {})",
+                block_number
+            ),
+        }
+        return;
+    }
+
+    warn!(
+        "⚠️ No events in the last {} blocks, couldn't check the keys filter",
+        KEY_FILTER_CHECK_BLOCKS
+    );
+}
+
+/// Refuses to start more than `MAX_STARTUP_LAG_BLOCKS` behind the tip, which
+/// usually means a stale or wrong database rather than a real backfill.
+async fn check_startup_lag(ctx: &Context, confirmed: bool) {
+    let Some(max_lag) = ctx.config.max_startup_lag_blocks else {
+        return;
+    };
+
+    let last_processed_block = get_last_processed_block(&ctx.control_pool).await;
+    let latest_block = ctx
+        .provider
+        .block_number()
+        .await
+        .expect("Failed to get latest block number");
+    let lag = latest_block.saturating_sub(last_processed_block);
+    if lag <= max_lag {
+        return;
+    }
+
+    if confirmed {
+        warn!(
+            "⚠️ Starting {} blocks behind the tip (cursor {}), backfill confirmed",
+            lag, last_processed_block
+        );
+        return;
+    }
+
+    error!(
+        "🛑 Cursor {} is {} blocks behind the tip {}, more than MAX_STARTUP_LAG_BLOCKS={}. Check DATABASE_URL points at the right database, or pass {} to start the backfill anyway.",
+        last_processed_block, lag, latest_block, max_lag, CONFIRM_LARGE_BACKFILL_FLAG
+    );
+    std::process::exit(1);
+}
+
+/// Runs once at startup. The fetch cursor can only be ahead of the commit
+/// cursor after a crash mid-pass; by default those blocks are replayed.
+async fn reconcile_cursors(ctx: &Context) {
+    let pool = &ctx.control_pool;
+    let (committed, fetched): (i64, i64) = sqlx::query_as(
+        "SELECT last_processed_block, last_fetched_block FROM block_state_trunks WHERE id = 1",
+    )
+    .fetch_one(pool)
+    .await
+    .expect("Failed to fetch cursors");
+
+    if fetched <= committed {
+        return;
+    }
+
+    if ctx.config.resume_from_fetch_cursor {
+        warn!(
+            "⏩ Resuming from fetch cursor {} (committed cursor was {})",
+            fetched, committed
+        );
+        update_last_processed_block(ctx, fetched as u64).await;
+    } else {
+        info!(
+            "🔁 Fetch cursor {} is ahead of committed cursor {}, replaying {} block(s)",
+            fetched,
+            committed,
+            fetched - committed
+        );
+    }
+}
+
+/// Fetches every contract's first page for `block_number` in batched
+/// requests. Returns `None`, so each contract is fetched on its own, when
+/// batching is off or the batch fails.
+async fn prefetch_first_pages(
+    ctx: &Context,
+    contract_addresses: &[Contract],
+    block_number: u64,
+) -> Option<std::vec::IntoIter<EventsPage>> {
+    let batch_client = ctx.batch_client.as_ref()?;
+    // Streaming may resume mid-block from a stored page token.
+    if ctx.config.streams_pages() || contract_addresses.len() < 2 {
+        return None;
+    }
+
+    let filters: Vec<EventFilter> = contract_addresses
+        .iter()
+        .map(|contract| event_filter(ctx, contract.address, block_number, block_number))
+        .collect();
+
+    match batch_client.first_pages(&filters, EVENTS_CHUNK_SIZE).await {
+        Ok(pages) => Some(pages.into_iter()),
+        Err(e) => {
+            warn!(
+                "⚠️ Batched get_events failed for block {}, falling back to single calls: {}",
+                block_number, e
+            );
+            None
+        }
+    }
+}
+
+/// `first_page`, when given, was already fetched by a batch request.
+async fn process_block(
+    ctx: &Context,
+    block_number: u64,
+    contract: &Contract,
+    mut first_page: Option<EventsPage>,
+) -> Result<Vec<EventTimeout>, ProcessError> {
+    let contract_address = contract.address;
+    let verbose = logs_block(ctx, block_number);
+    if verbose {
+        info!(
+            "Listening for events on {} (Felt: {:?}) in block {}",
+            contract.display_name(),
+            contract_address,
+            block_number,
+        );
+    }
+
+    let filter = event_filter(ctx, contract_address, block_number, block_number);
+    let streaming = ctx.config.streams_pages();
+
+    let mut continuation_token = if streaming {
+        pages::load(&ctx.pool, contract_address, block_number).await?
+    } else {
+        None
+    };
+    if let Some(token) = &continuation_token {
+        info!(
+            "Resuming block {} on {} from page token {}",
+            block_number,
+            contract.display_name(),
+            token
+        );
+    }
+
+    // Stored tokens are only meaningful to the RPC that issued them and may
+    // have expired, so one that is rejected restarts the block instead.
+    let mut resumed = continuation_token.is_some();
+    let mut fetched = Vec::new();
+    let mut event_count = 0;
+    if streaming {
+        loop {
+            let page = match first_page.take() {
+                Some(page) => page,
+                None => match fetch_events_page(ctx, &filter, continuation_token.clone()).await {
+                    Err(e) if resumed => {
+                        warn!(
+                            "⚠️ Stored page token for block {} on {} was rejected ({}), restarting from its first page",
+                            block_number,
+                            contract.display_name(),
+                            e
+                        );
+                        resumed = false;
+                        continuation_token = None;
+                        continue;
+                    }
+                    page => page?,
+                },
+            };
+            resumed = false;
+            event_count += page.events.len();
+            continuation_token = page.continuation_token;
+            let events = in_requested_block(ctx, block_number, page.events).await?;
+
+            let parsed = parse_events(ctx, contract, &events).await?;
+            apply_page(
+                ctx,
+                contract_address,
+                block_number,
+                parsed,
+                continuation_token.as_deref(),
+            )
+            .await?;
+
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+    } else {
+        // Without streaming, every page is fetched before any is used, so a
+        // failure partway through fails the whole block.
+        let pages = fetch_all_pages(first_page.take(), |token| {
+            fetch_events_page(ctx, &filter, token)
+        })
+        .await?;
+        for page in pages {
+            event_count += page.events.len();
+            fetched.extend(in_requested_block(ctx, block_number, page.events).await?);
+        }
+    }
+
+    if verbose {
+        info!("Number of EventTimeout events fetched: {}", event_count);
+    }
+    ctx.metrics.count(Metric::EventsFetched, event_count as u64);
+
+    if event_count == 0 && verbose {
+        info!(
+            "No EventTimeout events found for block {} on {}",
+            block_number,
+            contract.display_name()
+        );
+    }
+
+    let digests_needed =
+        ctx.config.retry_consistency != RetryConsistency::Off || ctx.config.content_hash_window > 0;
+    if digests_needed && !streaming {
+        check_consistency(ctx, block_number, contract, &fetched).await?;
+    }
+
+    if let (Some(shadow_provider), false) = (&ctx.shadow_provider, streaming) {
+        let mismatch = shadow::compare_events(
+            shadow_provider,
+            filter,
+            block_number,
+            EVENTS_CHUNK_SIZE,
+            &fetched,
+        )
+        .await;
+        if mismatch {
+            ctx.metrics.count(Metric::ShadowMismatches, 1);
+        }
+    }
+
+    if ctx.config.canonical_event_order {
+        sort_by_transaction_index(ctx, block_number, &mut fetched).await?;
+    }
+
+    Ok(parse_events(ctx, contract, &fetched).await?)
+}
+
+/// Compares a block's events with the previous attempt at the same block
+/// this session. A load-balanced RPC can answer a retry from a node in a
+/// different state, so a mismatch is reported, and with `finalized` the
+/// block is only accepted once it's on L1 or two attempts in a row agree.
+async fn check_consistency(
+    ctx: &Context,
+    block_number: u64,
+    contract: &Contract,
+    events: &[EmittedEvent],
+) -> Result<(), ProcessError> {
+    let digest = consistency::digest(events);
+    let previous = ctx
+        .fetch_digests
+        .replace(contract.address, block_number, digest);
+    if ctx.config.retry_consistency == RetryConsistency::Off
+        || previous.map_or(true, |previous| previous == digest)
+    {
+        return Ok(());
+    }
+
+    warn!(
+        "⚠️ RPC returned different events for block {} on {} than an earlier attempt ({} now)",
+        block_number,
+        contract.display_name(),
+        events.len()
+    );
+    if ctx.config.retry_consistency != RetryConsistency::Finalized {
+        return Ok(());
+    }
+
+    match blocks::fetch_header(&ctx.provider, block_number).await? {
+        Some(header) if header.accepted_on_l1 => {
+            info!(
+                "Block {} is accepted on L1, using the latest result",
+                block_number
+            );
+            Ok(())
+        }
+        _ => Err(ProcessError::Inconsistent { block_number }),
+    }
+}
+
+/// Quarantines events a buggy RPC returned for a block other than the one
+/// requested (or as pending), since applying them would desync the cursor.
+async fn in_requested_block(
+    ctx: &Context,
+    block_number: u64,
+    events: Vec<EmittedEvent>,
+) -> Result<Vec<EmittedEvent>, sqlx::Error> {
+    let (in_block, strays) = split_by_block(block_number, events);
+    for event in strays {
+        error!(
+            "🚨 RPC returned an event from block {:?} when asked for block {}, discarding it",
+            event.block_number, block_number
+        );
+        failed_events::record(ctx, &event, "block number outside requested range").await?;
+    }
+    Ok(in_block)
+}
+
+/// Splits `events` into those from `block_number`, in their original
+/// order, and the rest, pending ones included.
+fn split_by_block(
+    block_number: u64,
+    events: Vec<EmittedEvent>,
+) -> (Vec<EmittedEvent>, Vec<EmittedEvent>) {
+    events
+        .into_iter()
+        .partition(|event| event.block_number == Some(block_number))
+}
+
+/// Reorders a block's events, gathered from every page, by their
+/// transaction's position in the block, for providers that don't return
+/// pages in execution order. The sort is stable, so events of the same
+/// transaction keep the order they arrived in.
+async fn sort_by_transaction_index(
+    ctx: &Context,
+    block_number: u64,
+    events: &mut [EmittedEvent],
+) -> Result<(), ProviderError> {
+    if events.len() < 2 {
+        return Ok(());
+    }
+    let Some(header) = ctx.headers.get(&ctx.provider, block_number).await? else {
+        warn!(
+            "⚠️ Block {} is pending, keeping events in RPC order",
+            block_number
+        );
+        return Ok(());
+    };
+
+    if order_by_transactions(&header.transactions, events) {
+        warn!(
+            "🔀 Events for block {} arrived out of order, sorted by transaction index",
+            block_number
+        );
+    }
+
+    Ok(())
+}
+
+/// Sorts `events` by their transaction's position in `transactions`, with
+/// unknown transactions last. Returns whether they were out of order.
+fn order_by_transactions(transactions: &[Felt], events: &mut [EmittedEvent]) -> bool {
+    let positions: HashMap<Felt, usize> = transactions
+        .iter()
+        .enumerate()
+        .map(|(position, hash)| (*hash, position))
+        .collect();
+    let position = |event: &EmittedEvent| {
+        positions
+            .get(&event.transaction_hash)
+            .copied()
+            .unwrap_or(usize::MAX)
+    };
+    let was_ordered = events
+        .windows(2)
+        .all(|pair| position(&pair[0]) <= position(&pair[1]));
+    if !was_ordered {
+        events.sort_by_key(position);
+    }
+    !was_ordered
+}
+
+/// Follows continuation tokens from `first_page`, or from a fresh first
+/// page, to the last one. A page that can't be fetched fails the whole call,
+/// so a block is never used half-fetched.
+async fn fetch_all_pages<F, Fut>(
+    first_page: Option<EventsPage>,
+    mut fetch: F,
+) -> Result<Vec<EventsPage>, ProviderError>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<EventsPage, ProviderError>>,
+{
+    let mut pages = Vec::new();
+    let mut next = first_page;
+    loop {
+        let page = match next.take() {
+            Some(page) => page,
+            None => {
+                let token = pages
+                    .last()
+                    .and_then(|page: &EventsPage| page.continuation_token.clone());
+                fetch(token).await?
+            }
+        };
+        let last = page.continuation_token.is_none();
+        pages.push(page);
+        if last {
+            return Ok(pages);
+        }
+    }
+}
+
+async fn fetch_events_page(
+    ctx: &Context,
+    filter: &EventFilter,
+    continuation_token: Option<String>,
+) -> Result<EventsPage, ProviderError> {
+    let mut backoff = ctx.settings.in_pass_backoff();
+    loop {
+        match ctx
+            .provider
+            .get_events(
+                filter.clone(),
+                continuation_token.clone(),
+                EVENTS_CHUNK_SIZE,
+            )
+            .await
+        {
+            Ok(page) => return Ok(page),
+            Err(err) => {
+                error!("Error fetching events: {}", err);
+                if !backoff.retry().await {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+/// Parses EventTimeout resolutions out of `events`, writing any
+/// config-routed events straight to their tables along the way.
+async fn parse_events(
+    ctx: &Context,
+    contract: &Contract,
+    events: &[EmittedEvent],
+) -> Result<Vec<EventTimeout>, sqlx::Error> {
+    let preloaded_resolved = ctx.preloaded_resolved.read().unwrap().clone();
+
+    let layout = &abi::layout(ctx, contract.address).await;
+
+    let mut parsed = Vec::new();
+    for event in events {
+        let series = timeseries::find(&ctx.series, event);
+        if let Some(series) = series {
+            timeseries::record(ctx, series, event).await;
+        }
+        if let Some(route) = routing::find(&ctx.routes, event) {
+            if !routing::write(&ctx.pool, route, event).await? {
+                failed_events::record(ctx, event, "routed event missing a mapped field").await?;
+            }
+            continue;
+        }
+        if series.is_some() {
+            continue;
+        }
+
+        if let Some(event_address) = event.data.first() {
+            let event_address = format_address(&event_address.to_fixed_hex_string());
+            if preloaded_resolved.contains(&event_address) {
+                debug!(
+                    "Skipping event_address {} resolved before this pass",
+                    event_address
+                );
+                continue;
+            }
+        }
+
+        let mut event_finished = match resolution_from(ctx, contract, layout, event).await {
+            Ok(event_finished) => event_finished,
+            Err(reason) => {
+                failed_events::record(ctx, event, reason).await?;
+                continue;
+            }
+        };
+        set_resolved_by(ctx, &mut event_finished).await;
+
+        info!("✨ New EventFinished event: {:?}", event_finished);
+        parsed.push(event_finished);
+    }
+
+    Ok(parsed)
+}
+
+/// Turns an EventTimeout from `contract` into a resolution the way every
+/// pass applies it: completed through the fallback when its data is short,
+/// then checked against the allowed outcomes unless it is a cancel. A
+/// refused event comes back as the reason to record it under.
+async fn resolution_from(
+    ctx: &Context,
+    contract: &Contract,
+    layout: &EventLayout,
+    event: &EmittedEvent,
+) -> Result<EventTimeout, &'static str> {
+    let mut data: &[Felt] = &event.data;
+    let completed;
+    if data.len() < layout.min_len()
+        && ctx
+            .config
+            .resolution_fallback_contracts
+            .contains(&contract.address)
+    {
+        if let Some(full) = fallback::complete(ctx, contract, event).await {
+            completed = full;
+            data = &completed;
+        }
+    }
+
+    let Some(event_finished) =
+        parse_event_finished_event(data, event, layout, ctx.config.market_identity.market_id())
+    else {
+        error!(
+            "❌ Failed to parse EventFinished event with data: {:?}",
+            data
+        );
+        return Err("EventTimeout data too short");
+    };
+
+    let allowed_outcomes = contract
+        .allowed_outcomes
+        .as_deref()
+        .or(ctx.config.allowed_outcomes.as_deref());
+    let raw_outcome = layout.outcome.decode(data);
+    let cancelled = is_cancel(raw_outcome, ctx.config.cancel_outcome);
+    if let (Some(allowed), false) = (allowed_outcomes, cancelled) {
+        if !outcome_allowed(raw_outcome, allowed) {
+            error!(
+                "🚨 Outcome {:?} for {} is outside the allowed set {:?}, not applying it",
+                raw_outcome, event_finished.event_address, allowed
+            );
+            return Err("outcome outside allowed set");
+        }
+    }
+    Ok(event_finished)
+}
+
+/// Looks up the sender of the resolving transaction with
+/// `RESOLVED_BY_COLUMN`, so applying the resolution doesn't overwrite the
+/// stored resolver with NULL.
+async fn set_resolved_by(ctx: &Context, event: &mut EventTimeout) {
+    if ctx.config.resolved_by_column.is_some() {
+        event.resolved_by = senders::sender(ctx, event.transaction_hash)
+            .await
+            .map(|sender| sender.to_fixed_hex_string());
+    }
+}
+
+/// Checks the decoded outcome rather than the parsed `u8`, which silently
+/// truncates garbage values to 0.
+fn outcome_allowed(raw_outcome: Option<u64>, allowed: &[i64]) -> bool {
+    raw_outcome
+        .and_then(|outcome| i64::try_from(outcome).ok())
+        .map_or(false, |outcome| allowed.contains(&outcome))
+}
+
+/// Whether the decoded outcome is `CANCEL_OUTCOME`. Checked on the decoded
+/// value for the same reason as `outcome_allowed`, so garbage isn't taken
+/// for a cancel of 0.
+fn is_cancel(raw_outcome: Option<u64>, cancel_outcome: Option<u8>) -> bool {
+    matches!(
+        (raw_outcome, cancel_outcome),
+        (Some(outcome), Some(cancel)) if outcome == u64::from(cancel)
+    )
+}
+
+/// Streaming mode: applies one page of resolutions in the same transaction
+/// as the sub-cursor pointing at the next page, so a crash mid-pagination
+/// resumes from the first page that wasn't committed.
+async fn apply_page(
+    ctx: &Context,
+    contract_address: Felt,
+    block_number: u64,
+    events: Vec<EventTimeout>,
+    next_token: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let events = skip_resolved(ctx, events).await;
+
+    let to_apply = &events;
+    let written = retry_serialization_failures(ctx, || async move {
+        let mut tx = ctx.pool.begin().await?;
+        let mut written = Vec::with_capacity(to_apply.len());
+        for event in to_apply {
+            written.push(update_database_for_event_finished(event, &mut tx, &ctx.config).await?);
+        }
+        pages::save(&mut tx, contract_address, block_number, next_token).await?;
+        tx.commit().await?;
+        Ok(written)
+    })
+    .await?;
+
+    if !events.is_empty() {
+        info!("💾 Committed page of {} resolution(s)", events.len());
+    }
+    after_commit(ctx, &applied(events, &written));
+
+    Ok(())
+}
+
+fn event_filter(
+    ctx: &Context,
+    contract_address: Felt,
+    from_block: u64,
+    to_block: u64,
+) -> EventFilter {
+    let selectors = indexed_events(ctx)
+        .into_iter()
+        .map(|(_, selector)| selector)
+        .collect();
+
+    EventFilter {
+        from_block: Some(BlockId::Number(from_block)),
+        to_block: Some(BlockId::Number(to_block)),
+        address: Some(contract_address),
+        keys: Some(vec![selectors]),
+    }
+}
+
+/// Every event the indexer listens for, with its computed selector.
+fn indexed_events(ctx: &Context) -> Vec<(&str, Felt)> {
+    let mut events = vec![("EventTimeout", event_timeout_event_key())];
+    events.extend(
+        ctx.routes
+            .iter()
+            .map(|route| (route.event.as_str(), route.selector)),
+    );
+    for series in &ctx.series {
+        if !events
+            .iter()
+            .any(|(_, selector)| *selector == series.selector)
+        {
+            events.push((series.event.as_str(), series.selector));
+        }
+    }
+    events
+}
+
+fn log_event_filters(ctx: &Context, contract_addresses: &[Contract]) {
+    for (name, selector) in indexed_events(ctx) {
+        info!(
+            "🔎 Listening for {} (selector {})",
+            name,
+            selector.to_fixed_hex_string()
+        );
+    }
+
+    for contract in contract_addresses {
+        let filter = event_filter(ctx, contract.address, 0, 0);
+        info!(
+            "🔎 Filter for {}: keys {:?}",
+            contract.display_name(),
+            filter.keys.unwrap_or_default()
+        );
+    }
+}
+
+fn event_timeout_event_key() -> Felt {
+    let selector =
+        get_selector_from_name("EventTimeout").expect("Failed to compute event selector");
+    info!("EventTimeout selector: {:?}", selector);
+    selector
+}
+
+/// `market_id` is the data index of the market id with a composite
+/// identity; an event too short to hold it doesn't parse.
+fn parse_event_finished_event(
+    data: &[Felt],
+    event: &EmittedEvent,
+    layout: &EventLayout,
+    market_id: Option<&MarketId>,
+) -> Option<EventTimeout> {
+    let market_id = match market_id {
+        Some(market_id) => Some(data.get(market_id.data_index)?.to_biguint().to_string()),
+        None => None,
+    };
+    if data.len() >= layout.min_len() {
+        let event_address = format_address(&data[0].to_fixed_hex_string());
+        let event_outcome = layout
+            .outcome
+            .decode(data)
+            .and_then(|outcome| u8::try_from(outcome).ok())
+            .unwrap_or(0);
+        let timestamp = layout.timestamp.decode(data).unwrap_or(0);
+
+        Some(EventTimeout {
+            event_address,
+            event_outcome,
+            timestamp,
+            transaction_hash: event.transaction_hash,
+            block_number: event.block_number,
+            resolved_by: None,
+            market_id,
+        })
+    } else {
+        None
+    }
+}
+
+/// What `update_database_for_event_finished` did with a resolution. Only
+/// applied ones get the post-commit bookkeeping; a buffered one gets it
+/// when `orphans::reapply` applies it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Written {
+    Applied,
+    Buffered,
+}
+
+async fn update_database_for_event_finished(
+    event: &EventTimeout,
+    conn: &mut PgConnection,
+    config: &Config,
+) -> Result<Written, sqlx::Error> {
+    if let Some(function) = &config.resolve_function {
+        resolve_through_function(event, conn, function).await?;
+        enqueue_notification(event, conn, config).await?;
+        return Ok(Written::Applied);
+    }
+
+    let query = events_update_sql(config);
+    let mut update = sqlx::query(&query)
+        .bind(event.event_outcome as i32)
+        .bind(&event.event_address);
+    if config.resolved_by_column.is_some() {
+        update = update.bind(&event.resolved_by);
+    }
+    if config.indexed_by_version_column.is_some() {
+        update = update.bind(indexer_version());
+    }
+    if config.market_identity.market_id().is_some() {
+        update = update.bind(&event.market_id);
+    }
+    let updated = update.execute(&mut *conn).await.map_err(|e| {
+        error!("Failed to update events table: {}", e);
+        e
+    })?;
+    if updated.rows_affected() == 0 && config.buffer_orphan_resolutions {
+        orphans::buffer(event, conn).await?;
+        return Ok(Written::Buffered);
+    }
+    if updated.rows_affected() == 0 {
+        warn!(
+            "⚠️ No events row for event_address {}, nothing was marked resolved",
+            event.event_address
+        );
+        strict_violation(config, "resolution matched no events row")?;
+    }
+
+    info!(
+        "Updated events table for event_address: {}",
+        event.event_address
+    );
+
+    if config.validate_bet_values {
+        flag_out_of_range_bets(event, conn, config).await?;
+    }
+
+    let refund_query = refund_bets_sql(config);
+    let winners_query = winning_bets_sql(config);
+    let bets_update = if config.cancel_outcome == Some(event.event_outcome) {
+        info!(
+            "↩️ {} was cancelled, refunding every bet",
+            event.event_address
+        );
+        sqlx::query(&refund_query).bind(&event.event_address)
+    } else {
+        sqlx::query(&winners_query)
+            .bind(&event.event_address)
+            .bind(config.bet_mapping.winning_bet(event.event_outcome))
+    };
+    let bets_update = match config.market_identity.market_id() {
+        Some(_) => bets_update.bind(&event.market_id),
+        None => bets_update,
+    };
+    bets_update.execute(&mut *conn).await.map_err(|e| {
+        error!("Failed to update bets table: {}", e);
+        e
+    })?;
+
+    info!(
+        "Updated bets table for event_address: {}",
+        event.event_address
+    );
+
+    enqueue_notification(event, conn, config).await?;
+    Ok(Written::Applied)
+}
+
+/// Resolves the event bound as `$2` with the outcome bound as `$1`, then
+/// the resolver and indexer version when those columns are configured, and
+/// its market last with a composite identity.
+fn events_update_sql(config: &Config) -> String {
+    let mut extra_columns = String::new();
+    let mut next_param = 3;
+    for column in [
+        &config.resolved_by_column,
+        &config.indexed_by_version_column,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        extra_columns.push_str(&format!(", {} = ${}", column, next_param));
+        next_param += 1;
+    }
+    format!(
+        "UPDATE events SET {}, {} = $1::{}{} WHERE address = $2{}",
+        config.resolution_model.mark_resolved(),
+        config.outcome_column,
+        config.outcome_column_type,
+        extra_columns,
+        config.market_filter(|market_id| market_id.events_column.as_str(), next_param)
+    )
+}
+
+/// Refunds every bet on the cancelled event bound as `$1`, and its market
+/// as `$2` with a composite identity.
+fn refund_bets_sql(config: &Config) -> String {
+    format!(
+        "UPDATE bets SET {}
+        WHERE \"event_address\" = $1{}",
+        config.refund_assignments(),
+        config.market_filter(|market_id| market_id.bets_column.as_str(), 2)
+    )
+}
+
+/// Marks claimable the bets on the event bound as `$1` whose value is the
+/// winning bet bound as `$2`, and its market as `$3` with a composite
+/// identity.
+fn winning_bets_sql(config: &Config) -> String {
+    format!(
+        "UPDATE bets SET is_claimable = TRUE
+        WHERE \"event_address\" = $1 AND bet = $2{}",
+        config.market_filter(|market_id| market_id.bets_column.as_str(), 3)
+    )
+}
+
+/// SQL array of the `bets.bet` values that can win for the event bound as
+/// `$1`, and its market as `$2` with a composite identity, or `None` when
+/// its outcome set isn't known.
+fn valid_bets_sql(config: &Config) -> Option<String> {
+    let market_filter = config.market_filter(|market_id| market_id.events_column.as_str(), 2);
+    if config.bet_mapping == BetMapping::Binary {
+        return Some("ARRAY[0, 1]::BIGINT[]".to_string());
+    }
+
+    let per_contract = match (
+        &config.allowed_outcomes_column,
+        &config.outcome_count_column,
+    ) {
+        (Some(column), _) => Some(format!(
+            "(SELECT {}::BIGINT[] FROM events WHERE address = $1{})",
+            column, market_filter
+        )),
+        (None, Some(column)) => Some(format!(
+            "(SELECT ARRAY(SELECT generate_series(0, {} - 1))::BIGINT[]
+              FROM events WHERE address = $1{})",
+            column, market_filter
+        )),
+        (None, None) => None,
+    };
+    let global = config.allowed_outcomes.as_ref().map(|outcomes| {
+        let outcomes: Vec<String> = outcomes.iter().map(i64::to_string).collect();
+        format!("ARRAY[{}]::BIGINT[]", outcomes.join(", "))
+    });
+
+    match (per_contract, global) {
+        (Some(per_contract), Some(global)) => {
+            Some(format!("COALESCE({}, {})", per_contract, global))
+        }
+        (per_contract, global) => per_contract.or(global),
+    }
+}
+
+/// With `VALIDATE_BET_VALUES`, logs bets on the event whose `bet` value no
+/// outcome of its contract can produce. They point at corrupt data, so in
+/// strict mode the run stops instead.
+async fn flag_out_of_range_bets(
+    event: &EventTimeout,
+    conn: &mut PgConnection,
+    config: &Config,
+) -> Result<(), sqlx::Error> {
+    let Some(valid_bets) = valid_bets_sql(config) else {
+        return Ok(());
+    };
+
+    let query = format!(
+        "SELECT bet::BIGINT, COUNT(*) FROM bets
+         WHERE event_address = $1{} AND NOT (bet::BIGINT = ANY({}))
+         GROUP BY bet",
+        config.market_filter(|market_id| market_id.bets_column.as_str(), 2),
+        valid_bets
+    );
+    let mut invalid = sqlx::query_as::<_, (Option<i64>, i64)>(&query).bind(&event.event_address);
+    if config.market_identity.market_id().is_some() {
+        invalid = invalid.bind(&event.market_id);
+    }
+    let invalid = invalid.fetch_all(&mut *conn).await?;
+    if invalid.is_empty() {
+        return Ok(());
+    }
+
+    for (bet, count) in &invalid {
+        warn!(
+            "⚠️ {} bet(s) on {} have bet = {:?}, outside its outcome range",
+            count, event.event_address, bet
+        );
+    }
+    strict_violation(config, "bets hold a value outside the outcome range")
+}
+
+/// In outbox mode, the webhook notification commits or rolls back together
+/// with the resolution.
+async fn enqueue_notification(
+    event: &EventTimeout,
+    conn: &mut PgConnection,
+    config: &Config,
+) -> Result<(), sqlx::Error> {
+    if config.resolution_webhook_outbox && config.resolution_webhook_url.is_some() {
+        webhook::enqueue(event, conn).await.map_err(|e| {
+            error!("Failed to write outbox notification: {}", e);
+            e
+        })?;
+    }
+    Ok(())
+}
+
+/// Hands the resolution to a database function instead of running the
+/// inline UPDATEs, for teams that centralize writes in audited procedures.
+async fn resolve_through_function(
+    event: &EventTimeout,
+    conn: &mut PgConnection,
+    function: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!("SELECT {}($1, $2, $3)", function))
+        .bind(&event.event_address)
+        .bind(event.event_outcome as i32)
+        .bind(event.timestamp as i64)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to call {}: {}", function, e);
+            e
+        })?;
+
+    info!(
+        "Resolved event_address {} through {}",
+        event.event_address, function
+    );
+
+    Ok(())
+}
+
+/// The crate version, plus the commit it was built from when `build.rs`
+/// could determine it, e.g. `0.1.0+1a2b3c4`.
+fn indexer_version() -> String {
+    match option_env!("TRUNKS_GIT_COMMIT") {
+        Some(commit) if !commit.is_empty() => {
+            format!("{}+{}", env!("CARGO_PKG_VERSION"), commit)
+        }
+        _ => env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+/// With `STRICT_MODE`, turns a data anomaly into an error instead of
+/// logging it and moving on, so CI runs against known-good data fail. The
+/// error rolls back the write and fails the block like any other, and the
+/// run stops once the pass has unwound, see `exit_if_strict_violation`.
+fn strict_violation(config: &Config, anomaly: &str) -> Result<(), sqlx::Error> {
+    if !config.strict_mode {
+        return Ok(());
+    }
+    error!("🛑 Strict mode: {}, stopping", anomaly);
+    let _ = STRICT_VIOLATION.set(anomaly.to_string());
+    Err(sqlx::Error::Protocol(format!("strict mode: {}", anomaly)))
+}
+
+fn strict_violated() -> bool {
+    STRICT_VIOLATION.get().is_some()
+}
+
+/// Called by `main` once the run has been reported, so a strict-mode stop
+/// still gets the session summary and snapshot.
+fn exit_if_strict_violation() {
+    if let Some(anomaly) = STRICT_VIOLATION.get() {
+        error!("🛑 Stopped by strict mode: {}", anomaly);
+        std::process::exit(1);
+    }
+}
+
+fn format_address(address: &str) -> String {
+    let hex_str = if address.starts_with("0x") {
+        &address[2..]
+    } else {
+        address
+    };
+    let formatted = format!("0x{:0>64}", hex_str);
+
+    info!("Formatted address: {}", formatted);
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::MarketIdentity;
+    use sqlx::Connection;
+
+    fn resolution(event_address: &str, event_outcome: u8, transaction_hash: u64) -> EventTimeout {
+        EventTimeout {
+            event_address: event_address.to_string(),
+            event_outcome,
+            timestamp: 0,
+            transaction_hash: Felt::from(transaction_hash),
+            block_number: Some(1),
+            resolved_by: None,
+            market_id: None,
+        }
+    }
+
+    fn page(block_number: u64, continuation_token: Option<&str>) -> EventsPage {
+        EventsPage {
+            events: vec![EmittedEvent {
+                from_address: Felt::ONE,
+                keys: vec![event_timeout_event_key()],
+                data: vec![Felt::from(0xabcu64), Felt::ONE, Felt::ZERO],
+                block_hash: None,
+                block_number: Some(block_number),
+                transaction_hash: Felt::from(block_number),
+            }],
+            continuation_token: continuation_token.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_all_pages_fails_the_block_when_page_three_fails() {
+        let mut requested = Vec::new();
+        let result = fetch_all_pages(None, |token| {
+            requested.push(token.clone());
+            let call = requested.len();
+            async move {
+                match call {
+                    1 => Ok(page(5, Some("page-2"))),
+                    2 => Ok(page(5, Some("page-3"))),
+                    _ => Err(ProviderError::RateLimited),
+                }
+            }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            requested,
+            vec![None, Some("page-2".to_string()), Some("page-3".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_all_pages_continues_from_a_prefetched_first_page() {
+        let mut requested = Vec::new();
+        let pages = fetch_all_pages(Some(page(5, Some("page-2"))), |token| {
+            requested.push(token);
+            async { Ok(page(5, None)) }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(requested, vec![Some("page-2".to_string())]);
+    }
+
+    /// Each setup gets a pool of one connection, the smallest an instance
+    /// can run with, so a step that needed a second connection would hang.
+    /// Both run against a scratch schema of `DATABASE_URL` that is dropped
+    /// afterwards, so the real tables are never touched.
+    #[tokio::test]
+    #[ignore = "needs a Postgres DATABASE_URL"]
+    async fn concurrent_setups_both_finish() {
+        let url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let schema = format!("trunks_setup_test_{}", std::process::id());
+        let mut admin = PgConnection::connect(&url).await.unwrap();
+        sqlx::query(&format!("CREATE SCHEMA {}", schema))
+            .execute(&mut admin)
+            .await
+            .unwrap();
+
+        let connect = || {
+            let search_path = format!("SET search_path TO {}", schema);
+            PgPoolOptions::new()
+                .max_connections(1)
+                .after_connect(move |conn, _| {
+                    let search_path = search_path.clone();
+                    Box::pin(async move {
+                        sqlx::query(&search_path).execute(conn).await?;
+                        Ok(())
+                    })
+                })
+                .connect(&url)
+        };
+        let (first, second) = (connect().await.unwrap(), connect().await.unwrap());
+        for table in [
+            "CREATE TABLE events (address TEXT PRIMARY KEY, is_active BOOLEAN, outcome INTEGER)",
+            "CREATE TABLE bets (event_address TEXT, bet INTEGER, is_claimable BOOLEAN)",
+        ] {
+            sqlx::query(table).execute(&first).await.unwrap();
+        }
+        let config = Config::for_tests();
+
+        let finished = tokio::time::timeout(Duration::from_secs(30), async {
+            tokio::join!(
+                setup_database(&first, &config),
+                setup_database(&second, &config)
+            )
+        })
+        .await;
+        let cursors =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM block_state_trunks WHERE id = 1")
+                .fetch_one(&first)
+                .await;
+
+        first.close().await;
+        second.close().await;
+        sqlx::query(&format!("DROP SCHEMA {} CASCADE", schema))
+            .execute(&mut admin)
+            .await
+            .unwrap();
+        finished.expect("concurrent setups deadlocked");
+        assert_eq!(cursors.unwrap(), 1);
+    }
+
+    #[test]
+    fn group_by_transaction_keeps_a_multi_event_transaction_together() {
+        let events = vec![
+            resolution("0x1", 1, 7),
+            resolution("0x2", 0, 8),
+            resolution("0x3", 1, 7),
+            resolution("0x4", 1, 9),
+            resolution("0x5", 0, 7),
+        ];
+
+        let transactions = group_by_transaction(events);
+        let addresses: Vec<Vec<&str>> = transactions
+            .iter()
+            .map(|transaction| {
+                transaction
+                    .iter()
+                    .map(|event| event.event_address.as_str())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            addresses,
+            vec![vec!["0x1", "0x3", "0x5"], vec!["0x2"], vec!["0x4"]]
+        );
+    }
+
+    #[test]
+    fn events_shuffled_across_pages_are_sorted_into_block_order() {
+        let event = |transaction_hash: u64, data: u64| EmittedEvent {
+            data: vec![Felt::from(data)],
+            transaction_hash: Felt::from(transaction_hash),
+            ..page(5, None).events.remove(0)
+        };
+        // The provider returned transaction 2's events before transaction
+        // 1's, and split transaction 3 across both pages.
+        let first_page = vec![event(20, 0), event(30, 0), event(20, 1)];
+        let second_page = vec![event(10, 0), event(30, 1)];
+        let mut events: Vec<EmittedEvent> = first_page.into_iter().chain(second_page).collect();
+        let transactions = [Felt::from(10u64), Felt::from(20u64), Felt::from(30u64)];
+
+        assert!(order_by_transactions(&transactions, &mut events));
+        let order: Vec<(Felt, Felt)> = events
+            .iter()
+            .map(|event| (event.transaction_hash, event.data[0]))
+            .collect();
+        let expected: Vec<(Felt, Felt)> = [(10, 0), (20, 0), (20, 1), (30, 0), (30, 1)]
+            .into_iter()
+            .map(|(hash, data)| (Felt::from(hash as u64), Felt::from(data as u64)))
+            .collect();
+        assert_eq!(order, expected);
+        assert!(!order_by_transactions(&transactions, &mut events));
+    }
+
+    #[test]
+    fn events_outside_the_requested_block_are_split_off() {
+        let in_block = |transaction_hash: u64| EmittedEvent {
+            transaction_hash: Felt::from(transaction_hash),
+            ..page(5, None).events.remove(0)
+        };
+        let future = page(6, None).events.remove(0);
+        let pending = EmittedEvent {
+            block_number: None,
+            ..page(5, None).events.remove(0)
+        };
+
+        let (kept, strays) = split_by_block(
+            5,
+            vec![in_block(1), future, in_block(2), pending, in_block(3)],
+        );
+        let kept: Vec<Felt> = kept.iter().map(|event| event.transaction_hash).collect();
+        let strays: Vec<Option<u64>> = strays.iter().map(|event| event.block_number).collect();
+        assert_eq!(
+            kept,
+            vec![Felt::from(1u64), Felt::from(2u64), Felt::from(3u64)]
+        );
+        assert_eq!(strays, vec![Some(6), None]);
+    }
+
+    #[test]
+    fn only_reindex_contract_queries_include_inactive_contracts() {
+        let mut config = Config::for_tests();
+
+        assert!(contracts_query(&config, false).ends_with("WHERE is_active = true"));
+        assert!(contracts_query(&config, true).ends_with("WHERE TRUE"));
+
+        config.resolution_model = ResolutionModel::Soft;
+        assert!(contracts_query(&config, false)
+            .ends_with("WHERE is_active = true AND resolved_at IS NULL"));
+        assert!(contracts_query(&config, true).ends_with("WHERE TRUE"));
+    }
+
+    #[test]
+    fn valid_bets_follow_the_bet_mapping_and_outcome_sources() {
+        let mut config = Config::for_tests();
+        config.allowed_outcomes = Some(vec![0, 1, 2, 3]);
+        assert_eq!(valid_bets_sql(&config).unwrap(), "ARRAY[0, 1]::BIGINT[]");
+
+        config.bet_mapping = BetMapping::Direct;
+        assert_eq!(
+            valid_bets_sql(&config).unwrap(),
+            "ARRAY[0, 1, 2, 3]::BIGINT[]"
+        );
+
+        config.outcome_count_column = Some("outcome_count".to_string());
+        let sql = valid_bets_sql(&config).unwrap();
+        assert!(
+            sql.starts_with("COALESCE((SELECT ARRAY(SELECT generate_series(0, outcome_count - 1))")
+        );
+        assert!(sql.ends_with(", ARRAY[0, 1, 2, 3]::BIGINT[])"));
+
+        config.allowed_outcomes = None;
+        config.outcome_count_column = None;
+        assert_eq!(valid_bets_sql(&config), None);
+    }
+
+    /// Runs against `DATABASE_URL` inside a transaction that is rolled back.
+    #[tokio::test]
+    #[ignore = "needs a Postgres DATABASE_URL"]
+    async fn out_of_range_bet_fails_the_resolution_in_strict_mode() {
+        let url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let mut conn = PgConnection::connect(&url).await.unwrap();
+        let mut tx = conn.begin().await.unwrap();
+        sqlx::query("CREATE TEMP TABLE bets (event_address TEXT, bet INTEGER)")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO bets VALUES ('0xabc', 0), ('0xabc', 1), ('0xdef', 7)")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        let mut config = Config::for_tests();
+        config.strict_mode = true;
+
+        assert!(
+            flag_out_of_range_bets(&resolution("0xabc", 1, 7), &mut tx, &config)
+                .await
+                .is_ok()
+        );
+        sqlx::query("INSERT INTO bets VALUES ('0xabc', 7)")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        assert!(
+            flag_out_of_range_bets(&resolution("0xabc", 1, 7), &mut tx, &config)
+                .await
+                .is_err()
+        );
+    }
+
+    fn composite(config: &mut Config) {
+        config.market_identity = MarketIdentity::Composite(MarketId {
+            data_index: 3,
+            events_column: "market_id".to_string(),
+            bets_column: "bet_market".to_string(),
+        });
+    }
+
+    #[test]
+    fn bets_updates_number_the_market_placeholder_after_their_own() {
+        let mut config = Config::for_tests();
+        assert!(refund_bets_sql(&config).ends_with("\"event_address\" = $1"));
+        assert!(winning_bets_sql(&config).ends_with("\"event_address\" = $1 AND bet = $2"));
+
+        composite(&mut config);
+        assert!(
+            refund_bets_sql(&config).ends_with("\"event_address\" = $1 AND bet_market::TEXT = $2")
+        );
+        assert!(winning_bets_sql(&config)
+            .ends_with("\"event_address\" = $1 AND bet = $2 AND bet_market::TEXT = $3"));
+    }
+
+    fn finished_event(data: Vec<Felt>) -> EmittedEvent {
+        EmittedEvent {
+            from_address: Felt::ONE,
+            keys: vec![event_timeout_event_key()],
+            data,
+            block_hash: None,
+            block_number: Some(1),
+            transaction_hash: Felt::TWO,
+        }
+    }
+
+    #[test]
+    fn finished_event_without_a_market_id_parses_the_layout_only() {
+        let event = finished_event(vec![Felt::from(0xabcu64), Felt::ONE, Felt::from(42u64)]);
+        let parsed =
+            parse_event_finished_event(&event.data, &event, &EventLayout::default(), None).unwrap();
+        assert_eq!(parsed.event_outcome, 1);
+        assert_eq!(parsed.timestamp, 42);
+        assert_eq!(parsed.market_id, None);
+    }
+
+    #[test]
+    fn finished_event_reads_the_market_id_from_its_data_index() {
+        let market_id = MarketId {
+            data_index: 3,
+            events_column: "market_id".to_string(),
+            bets_column: "bet_market".to_string(),
+        };
+        let event = finished_event(vec![
+            Felt::from(0xabcu64),
+            Felt::ONE,
+            Felt::from(42u64),
+            Felt::from(1234u64),
+        ]);
+        let parsed = parse_event_finished_event(
+            &event.data,
+            &event,
+            &EventLayout::default(),
+            Some(&market_id),
+        )
+        .unwrap();
+        assert_eq!(parsed.market_id.as_deref(), Some("1234"));
+
+        let short = finished_event(vec![Felt::from(0xabcu64), Felt::ONE, Felt::from(42u64)]);
+        assert!(parse_event_finished_event(
+            &short.data,
+            &short,
+            &EventLayout::default(),
+            Some(&market_id),
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn events_update_numbers_the_market_placeholder_after_the_optional_columns() {
+        let mut config = Config::for_tests();
+        assert_eq!(
+            config.market_filter(|market_id| market_id.events_column.as_str(), 3),
+            ""
+        );
+        assert!(events_update_sql(&config).ends_with("WHERE address = $2"));
+
+        composite(&mut config);
+        assert!(events_update_sql(&config).ends_with("WHERE address = $2 AND market_id::TEXT = $3"));
+
+        config.resolved_by_column = Some("resolved_by".to_string());
+        config.indexed_by_version_column = Some("indexed_by".to_string());
+        let query = events_update_sql(&config);
+        assert!(query.contains(", resolved_by = $3, indexed_by = $4 WHERE"));
+        assert!(query.ends_with("WHERE address = $2 AND market_id::TEXT = $5"));
+    }
+
+    #[test]
+    fn dedup_key_tells_apart_outcomes_and_transactions() {
+        let orphaned = resolution("0xabc", 1, 7);
+        assert_eq!(orphaned.dedup_key(), resolution("0xabc", 1, 7).dedup_key());
+        assert_ne!(orphaned.dedup_key(), resolution("0xabc", 0, 7).dedup_key());
+        assert_ne!(orphaned.dedup_key(), resolution("0xabc", 1, 8).dedup_key());
+    }
+
+    #[test]
+    fn outcome_allowed_accepts_an_outcome_in_the_set() {
+        assert!(outcome_allowed(Some(1), &[0, 1]));
+    }
+
+    #[test]
+    fn outcome_allowed_rejects_an_outcome_outside_the_set() {
+        assert!(!outcome_allowed(Some(2), &[0, 1]));
+    }
+
+    #[test]
+    fn outcome_allowed_rejects_a_felt_that_does_not_fit_i64() {
+        assert!(!outcome_allowed(Some(u64::MAX), &[0, 1, -1]));
+        assert!(!outcome_allowed(None, &[0, 1]));
+    }
+
+    #[test]
+    fn is_cancel_matches_only_the_decoded_cancel_outcome() {
+        assert!(is_cancel(Some(0), Some(0)));
+        assert!(!is_cancel(Some(1), Some(0)));
+        assert!(!is_cancel(Some(256), Some(0)));
+        assert!(!is_cancel(None, Some(0)));
+        assert!(!is_cancel(Some(0), None));
+    }
 }
@@ -0,0 +1,294 @@
+use log::{error, info, warn};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::net::{TcpListener as StdTcpListener, UdpSocket};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Every metric the indexer reports. Backends only decide how these are
+/// encoded, so adding one here makes it available everywhere.
+#[derive(Debug, Clone, Copy)]
+pub enum Metric {
+    BlocksProcessed,
+    EventsFetched,
+    ResolutionsCommitted,
+    FailedEvents,
+    ContractsQuarantined,
+    /// Write transactions rerun after a serialization failure or deadlock.
+    SerializationRetries,
+    /// Contract blocks where the shadow RPC returned different events.
+    ShadowMismatches,
+    /// Blocks between the cursor and the tip at the start of a pass.
+    Lag,
+    PassDuration,
+}
+
+const ALL_METRICS: [Metric; 9] = [
+    Metric::BlocksProcessed,
+    Metric::EventsFetched,
+    Metric::ResolutionsCommitted,
+    Metric::FailedEvents,
+    Metric::ContractsQuarantined,
+    Metric::SerializationRetries,
+    Metric::ShadowMismatches,
+    Metric::Lag,
+    Metric::PassDuration,
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl Metric {
+    fn name(&self) -> &'static str {
+        match self {
+            Metric::BlocksProcessed => "blocks_processed",
+            Metric::EventsFetched => "events_fetched",
+            Metric::ResolutionsCommitted => "resolutions_committed",
+            Metric::FailedEvents => "failed_events",
+            Metric::ContractsQuarantined => "contracts_quarantined",
+            Metric::SerializationRetries => "serialization_retries",
+            Metric::ShadowMismatches => "shadow_mismatches",
+            Metric::Lag => "lag_blocks",
+            Metric::PassDuration => "pass_duration_ms",
+        }
+    }
+
+    fn kind(&self) -> Kind {
+        match self {
+            Metric::BlocksProcessed
+            | Metric::EventsFetched
+            | Metric::ResolutionsCommitted
+            | Metric::FailedEvents
+            | Metric::ContractsQuarantined
+            | Metric::SerializationRetries
+            | Metric::ShadowMismatches => Kind::Counter,
+            Metric::Lag => Kind::Gauge,
+            Metric::PassDuration => Kind::Histogram,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsBackend {
+    None,
+    Prometheus,
+    Statsd,
+}
+
+impl FromStr for MetricsBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Ok(MetricsBackend::None),
+            "prometheus" => Ok(MetricsBackend::Prometheus),
+            "statsd" => Ok(MetricsBackend::Statsd),
+            other => Err(format!("unknown metrics backend '{}'", other)),
+        }
+    }
+}
+
+/// Fire-and-forget metric reporting. Send failures are logged and never
+/// affect indexing.
+pub struct Metrics {
+    statsd: Option<Statsd>,
+    prometheus: Option<Arc<Registry>>,
+}
+
+struct Statsd {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+/// Current values behind the Prometheus endpoint. Histograms are exposed
+/// as summaries, with only their sum and count.
+#[derive(Default)]
+struct Registry {
+    values: Mutex<HashMap<&'static str, Sample>>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Sample {
+    value: u64,
+    count: u64,
+}
+
+impl Metrics {
+    pub fn new(
+        backend: MetricsBackend,
+        prometheus_addr: &str,
+        statsd_addr: &str,
+        prefix: &str,
+    ) -> Self {
+        let prometheus = (backend == MetricsBackend::Prometheus).then(|| {
+            let registry = Arc::new(Registry::default());
+            // Prometheus is on by default, so a port already taken, e.g. by
+            // another instance on the same host, only costs the endpoint.
+            match StdTcpListener::bind(prometheus_addr)
+                .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+                .and_then(TcpListener::from_std)
+            {
+                Ok(listener) => {
+                    info!(
+                        "📊 Serving Prometheus metrics on http://{}/metrics",
+                        prometheus_addr
+                    );
+                    tokio::spawn(serve(listener, registry.clone()));
+                }
+                Err(e) => error!(
+                    "Failed to serve Prometheus metrics on {}: {}",
+                    prometheus_addr, e
+                ),
+            }
+            registry
+        });
+        let statsd = match backend {
+            MetricsBackend::None | MetricsBackend::Prometheus => None,
+            MetricsBackend::Statsd => {
+                let socket = UdpSocket::bind("0.0.0.0:0")
+                    .and_then(|socket| socket.connect(statsd_addr).map(|()| socket))
+                    .and_then(|socket| socket.set_nonblocking(true).map(|()| socket))
+                    .unwrap_or_else(|e| panic!("Invalid STATSD_ADDR '{}': {}", statsd_addr, e));
+                info!("📊 Sending StatsD metrics to {}", statsd_addr);
+                Some(Statsd {
+                    socket,
+                    prefix: prefix.to_string(),
+                })
+            }
+        };
+
+        Metrics { statsd, prometheus }
+    }
+
+    pub fn count(&self, metric: Metric, value: u64) {
+        self.record(metric, value);
+    }
+
+    pub fn gauge(&self, metric: Metric, value: u64) {
+        self.record(metric, value);
+    }
+
+    pub fn timing(&self, metric: Metric, duration: Duration) {
+        self.record(metric, duration.as_millis() as u64);
+    }
+
+    fn record(&self, metric: Metric, value: u64) {
+        if let Some(registry) = &self.prometheus {
+            registry.record(metric, value);
+        }
+        let Some(statsd) = &self.statsd else {
+            return;
+        };
+
+        let kind = match metric.kind() {
+            Kind::Counter => "c",
+            Kind::Gauge => "g",
+            Kind::Histogram => "ms",
+        };
+        let line = format!("{}.{}:{}|{}", statsd.prefix, metric.name(), value, kind);
+        if let Err(e) = statsd.socket.send(line.as_bytes()) {
+            warn!("Failed to send metric {}: {}", metric.name(), e);
+        }
+    }
+}
+
+impl Registry {
+    fn record(&self, metric: Metric, value: u64) {
+        let mut values = self.values.lock().unwrap();
+        let sample = values.entry(metric.name()).or_default();
+        match metric.kind() {
+            Kind::Counter | Kind::Histogram => sample.value += value,
+            Kind::Gauge => sample.value = value,
+        }
+        sample.count += 1;
+    }
+
+    /// Every metric in the text exposition format, named
+    /// `trunks_<metric>`, with counters suffixed `_total`.
+    fn render(&self) -> String {
+        let values = self.values.lock().unwrap();
+        let mut body = String::new();
+        for metric in ALL_METRICS {
+            let sample = values.get(metric.name()).copied().unwrap_or_default();
+            let name = format!("trunks_{}", metric.name());
+            let _ = match metric.kind() {
+                Kind::Counter => write!(
+                    body,
+                    "# TYPE {0}_total counter\n{0}_total {1}\n",
+                    name, sample.value
+                ),
+                Kind::Gauge => write!(body, "# TYPE {0} gauge\n{0} {1}\n", name, sample.value),
+                Kind::Histogram => write!(
+                    body,
+                    "# TYPE {0} summary\n{0}_sum {1}\n{0}_count {2}\n",
+                    name, sample.value, sample.count
+                ),
+            };
+        }
+        body
+    }
+}
+
+/// Answers `GET /metrics` with the current values and anything else with a
+/// 404. Each connection gets a single response and is closed.
+async fn serve(listener: TcpListener, registry: Arc<Registry>) {
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Failed to accept a metrics connection: {}", e);
+                continue;
+            }
+        };
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            let read = stream.read(&mut request).await.unwrap_or(0);
+            let response = if request[..read].starts_with(b"GET /metrics") {
+                let body = registry.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                warn!("Failed to answer a metrics scrape: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_exposes_every_metric_by_kind() {
+        let registry = Registry::default();
+        registry.record(Metric::BlocksProcessed, 3);
+        registry.record(Metric::BlocksProcessed, 2);
+        registry.record(Metric::Lag, 40);
+        registry.record(Metric::Lag, 7);
+        registry.record(Metric::PassDuration, 120);
+        registry.record(Metric::PassDuration, 30);
+
+        let body = registry.render();
+        assert!(body.contains("# TYPE trunks_blocks_processed_total counter\n"));
+        assert!(body.contains("trunks_blocks_processed_total 5\n"));
+        assert!(body.contains("trunks_failed_events_total 0\n"));
+        assert!(body.contains("trunks_lag_blocks 7\n"));
+        assert!(body.contains("trunks_pass_duration_ms_sum 150\n"));
+        assert!(body.contains("trunks_pass_duration_ms_count 2\n"));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::config::Config;
+use crate::{after_commit, update_database_for_event_finished, Context, EventTimeout, Written};
+use log::{error, info};
+use sqlx::{Connection, PgConnection, Row};
+use starknet::core::types::Felt;
+
+/// Holds a resolution whose events row doesn't exist yet, in the same
+/// transaction that found nothing to update. A later resolution for the
+/// same address replaces it.
+pub async fn buffer(event: &EventTimeout, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO pending_resolutions
+            (event_address, event_outcome, event_timestamp, transaction_hash,
+             block_number, resolved_by)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (event_address) DO UPDATE SET
+            event_outcome = EXCLUDED.event_outcome,
+            event_timestamp = EXCLUDED.event_timestamp,
+            transaction_hash = EXCLUDED.transaction_hash,
+            block_number = EXCLUDED.block_number,
+            resolved_by = EXCLUDED.resolved_by,
+            buffered_at = now()",
+    )
+    .bind(&event.event_address)
+    .bind(event.event_outcome as i16)
+    .bind(event.timestamp as i64)
+    .bind(event.transaction_hash.to_fixed_hex_string())
+    .bind(event.block_number.map(|n| n as i64))
+    .bind(&event.resolved_by)
+    .execute(&mut *conn)
+    .await?;
+
+    info!(
+        "⏸️ No events row for {} yet, holding its resolution until one appears",
+        event.event_address
+    );
+    Ok(())
+}
+
+/// Applies every buffered resolution whose events row now exists, removing
+/// it from the buffer in the same transaction.
+pub async fn reapply(ctx: &Context) {
+    let mut conn = match ctx.pool.acquire().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to load pending resolutions: {}", e);
+            return;
+        }
+    };
+    let ready = match ready(&mut conn).await {
+        Ok(ready) => ready,
+        Err(e) => {
+            error!("Failed to load pending resolutions: {}", e);
+            return;
+        }
+    };
+
+    for event in ready {
+        match apply(&mut conn, &ctx.config, &event).await {
+            Ok(Written::Applied) => {
+                info!("▶️ Applied held resolution for {}", event.event_address);
+                after_commit(ctx, std::slice::from_ref(&event));
+            }
+            Ok(Written::Buffered) => {}
+            Err(e) => error!(
+                "Failed to apply held resolution for {}: {}",
+                event.event_address, e
+            ),
+        }
+    }
+}
+
+/// Applies one held resolution and drops it from the buffer. If its events
+/// row has gone again, the transaction is rolled back and it stays held.
+async fn apply(
+    conn: &mut PgConnection,
+    config: &Config,
+    event: &EventTimeout,
+) -> Result<Written, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+    if update_database_for_event_finished(event, &mut tx, config).await? == Written::Buffered {
+        return Ok(Written::Buffered);
+    }
+    sqlx::query("DELETE FROM pending_resolutions WHERE event_address = $1")
+        .bind(&event.event_address)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(Written::Applied)
+}
+
+async fn ready(conn: &mut PgConnection) -> Result<Vec<EventTimeout>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT p.* FROM pending_resolutions p
+         WHERE EXISTS (SELECT 1 FROM events e WHERE e.address = p.event_address)
+         ORDER BY p.block_number",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let transaction_hash: String = row.get("transaction_hash");
+            Some(EventTimeout {
+                event_address: row.get("event_address"),
+                event_outcome: u8::try_from(row.get::<i16, _>("event_outcome")).ok()?,
+                timestamp: row.get::<i64, _>("event_timestamp") as u64,
+                transaction_hash: Felt::from_hex(&transaction_hash).ok()?,
+                block_number: row.get::<Option<i64>, _>("block_number").map(|n| n as u64),
+                resolved_by: row.get("resolved_by"),
+                market_id: None,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs against `DATABASE_URL` inside a transaction that is rolled back,
+    /// with temporary `events` and `bets` tables shadowing any real ones.
+    #[tokio::test]
+    #[ignore = "needs a Postgres DATABASE_URL"]
+    async fn buffered_resolution_is_applied_once_its_events_row_exists() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let mut conn = PgConnection::connect(&url).await.unwrap();
+        crate::MIGRATOR.run(&mut conn).await.unwrap();
+        let mut conn = conn.begin().await.unwrap();
+        sqlx::query(
+            "CREATE TEMP TABLE events (address TEXT PRIMARY KEY, is_active BOOLEAN, outcome INTEGER)",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        sqlx::query(
+            "CREATE TEMP TABLE bets (event_address TEXT, bet INTEGER, is_claimable BOOLEAN DEFAULT FALSE)",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO bets (event_address, bet) VALUES ('0xabc', 0), ('0xabc', 1)")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let mut config = Config::for_tests();
+        config.buffer_orphan_resolutions = true;
+        let event = EventTimeout {
+            event_address: "0xabc".to_string(),
+            event_outcome: 1,
+            timestamp: 1_700_000_000,
+            transaction_hash: Felt::from(7u64),
+            block_number: Some(42),
+            resolved_by: None,
+            market_id: None,
+        };
+
+        let written = update_database_for_event_finished(&event, &mut conn, &config)
+            .await
+            .unwrap();
+        assert_eq!(written, Written::Buffered);
+        assert!(ready(&mut conn).await.unwrap().is_empty());
+
+        sqlx::query("INSERT INTO events (address, is_active) VALUES ('0xabc', TRUE)")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let held = ready(&mut conn).await.unwrap();
+        assert_eq!(held.len(), 1);
+        assert_eq!(
+            apply(&mut conn, &config, &held[0]).await.unwrap(),
+            Written::Applied
+        );
+
+        let (is_active, outcome): (bool, i32) =
+            sqlx::query_as("SELECT is_active, outcome FROM events WHERE address = '0xabc'")
+                .fetch_one(&mut conn)
+                .await
+                .unwrap();
+        assert!(!is_active);
+        assert_eq!(outcome, 1);
+        let claimable: Vec<i32> =
+            sqlx::query_scalar("SELECT bet FROM bets WHERE is_claimable ORDER BY bet")
+                .fetch_all(&mut conn)
+                .await
+                .unwrap();
+        assert_eq!(claimable, vec![1]);
+        let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pending_resolutions")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(pending, 0);
+    }
+}
@@ -0,0 +1,56 @@
+use sqlx::postgres::PgConnection;
+use sqlx::{Pool, Postgres};
+use starknet::core::types::Felt;
+
+/// Continuation token to resume from for `contract_address` in
+/// `block_number`, if a previous streaming pass was interrupted there.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    contract_address: Felt,
+    block_number: u64,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT continuation_token FROM page_cursors
+         WHERE contract_address = $1 AND block_number = $2",
+    )
+    .bind(contract_address.to_fixed_hex_string())
+    .bind(block_number as i64)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Records the next page to fetch, or clears the sub-cursor once the last
+/// page has been applied.
+pub async fn save(
+    conn: &mut PgConnection,
+    contract_address: Felt,
+    block_number: u64,
+    next_token: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    match next_token {
+        Some(token) => {
+            sqlx::query(
+                "INSERT INTO page_cursors (contract_address, block_number, continuation_token)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (contract_address, block_number) DO UPDATE
+                 SET continuation_token = EXCLUDED.continuation_token, updated_at = now()",
+            )
+            .bind(contract_address.to_fixed_hex_string())
+            .bind(block_number as i64)
+            .bind(token)
+            .execute(conn)
+            .await?;
+        }
+        None => {
+            sqlx::query(
+                "DELETE FROM page_cursors WHERE contract_address = $1 AND block_number = $2",
+            )
+            .bind(contract_address.to_fixed_hex_string())
+            .bind(block_number as i64)
+            .execute(conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use sqlx::{Pool, Postgres, Row};
+use starknet::core::types::Felt;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Counts consecutive failures of each contract on the block it is stuck
+/// on, so a persistently failing contract can be set aside instead of
+/// holding the cursor back for everyone else.
+pub struct Quarantine {
+    after: u32,
+    failures: Mutex<HashMap<Felt, (u64, u32)>>,
+}
+
+impl Quarantine {
+    pub fn new(after: u32) -> Self {
+        Quarantine {
+            after,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a failure of `contract` on `block_number`. Returns `true` once
+    /// it has failed that block `after` times in a row.
+    pub fn failed(&self, contract: Felt, block_number: u64) -> bool {
+        let mut failures = self.failures.lock().unwrap();
+        let entry = failures.entry(contract).or_insert((block_number, 0));
+        if entry.0 != block_number {
+            *entry = (block_number, 0);
+        }
+        entry.1 += 1;
+
+        if entry.1 >= self.after {
+            failures.remove(&contract);
+            return true;
+        }
+        false
+    }
+}
+
+/// Quarantined contracts and the next block each still has to catch up on.
+pub async fn held(pool: &Pool<Postgres>) -> Result<Vec<(Felt, u64)>, sqlx::Error> {
+    let rows = sqlx::query("SELECT contract_address, next_block FROM quarantined_contracts")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let address: String = row.get("contract_address");
+            let next_block: i64 = row.get("next_block");
+            Some((Felt::from_hex(&address).ok()?, next_block as u64))
+        })
+        .collect())
+}
+
+pub async fn hold(
+    pool: &Pool<Postgres>,
+    contract: Felt,
+    block_number: u64,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO quarantined_contracts (contract_address, next_block, reason)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (contract_address) DO NOTHING",
+    )
+    .bind(contract.to_fixed_hex_string())
+    .bind(block_number as i64)
+    .bind(reason)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn advance(
+    pool: &Pool<Postgres>,
+    contract: Felt,
+    next_block: u64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE quarantined_contracts SET next_block = $2 WHERE contract_address = $1")
+        .bind(contract.to_fixed_hex_string())
+        .bind(next_block as i64)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn release(pool: &Pool<Postgres>, contract: Felt) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM quarantined_contracts WHERE contract_address = $1")
+        .bind(contract.to_fixed_hex_string())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use crate::blocks::{self, BlockHeader};
+use crate::{consistency, event_filter, fetch_events_page, format_address, Context, EventTimeout};
+use log::{error, info, warn};
+use starknet::core::types::Felt;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Resolutions applied this session from blocks still inside the reorg
+/// window, so a rollback can name the ones that came from orphaned blocks.
+/// Kept in memory only, so ones applied before a restart are not known.
+#[derive(Default)]
+pub struct RecentResolutions {
+    by_block: Mutex<BTreeMap<u64, Vec<String>>>,
+}
+
+impl RecentResolutions {
+    /// Records `event` and forgets blocks more than `window` below it.
+    pub fn record(&self, event: &EventTimeout, window: u64) {
+        let Some(block_number) = event.block_number else {
+            return;
+        };
+        let mut by_block = self.by_block.lock().unwrap();
+        by_block.entry(block_number).or_default().push(format!(
+            "{} (outcome {}, tx {})",
+            event.identity(),
+            event.event_outcome,
+            event.transaction_hash.to_fixed_hex_string()
+        ));
+        let newest = *by_block.keys().next_back().unwrap();
+        *by_block = by_block.split_off(&newest.saturating_sub(window).saturating_add(1));
+    }
+
+    /// Removes and returns the resolutions recorded from blocks after
+    /// `fork_point`.
+    fn orphaned(&self, fork_point: u64) -> Vec<String> {
+        let mut by_block = self.by_block.lock().unwrap();
+        by_block
+            .split_off(&(fork_point + 1))
+            .into_values()
+            .flatten()
+            .collect()
+    }
+}
+
+/// Compares the stored window of recent block hashes against the chain,
+/// newest first, and returns the block to roll the cursor back to if a fork
+/// is detected. Since hashes chain, a matching newest block means the whole
+/// window is still canonical, so the steady-state cost is one header fetch.
+pub async fn detect_fork(ctx: &Context) -> Option<u64> {
+    let stored: Vec<(i64, String)> = match sqlx::query_as(
+        "SELECT block_number, block_hash FROM recent_blocks ORDER BY block_number DESC",
+    )
+    .fetch_all(&ctx.control_pool)
+    .await
+    {
+        Ok(stored) => stored,
+        Err(e) => {
+            error!("Failed to load recent block hashes: {}", e);
+            return None;
+        }
+    };
+
+    let newest = stored
+        .first()
+        .map(|(block_number, _)| *block_number as u64)?;
+    for (block_number, block_hash) in &stored {
+        let block_number = *block_number as u64;
+        let header = match blocks::fetch_header(&ctx.provider, block_number).await {
+            Ok(Some(header)) => header,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Failed to verify block {}: {}", block_number, e);
+                return None;
+            }
+        };
+
+        if hash_hex(&header.block_hash) == *block_hash {
+            if block_number == newest {
+                return None;
+            }
+            warn!(
+                "🍴 Reorg detected: block {} is the last one still canonical",
+                block_number
+            );
+            return Some(block_number);
+        }
+    }
+
+    let oldest = stored
+        .last()
+        .map(|(block_number, _)| *block_number as u64)?;
+    error!(
+        "🍴 Reorg deeper than the recent block window (no match down to block {})",
+        oldest
+    );
+    Some(oldest.saturating_sub(1))
+}
+
+/// Forgets every stored hash above `fork_point`. Resolutions already
+/// applied from the orphaned blocks are left in `events` and `bets`: their
+/// previous state isn't kept, and a different outcome on the canonical chain
+/// overwrites the events row but not the bets it made claimable. They are
+/// logged and alerted on so they can be checked by hand.
+pub async fn rollback(ctx: &Context, fork_point: u64) -> Result<(), sqlx::Error> {
+    let orphaned = ctx.recent_resolutions.orphaned(fork_point);
+    if !orphaned.is_empty() {
+        let message = format!(
+            "{} resolution(s) applied from blocks after {} were orphaned by a reorg and need a manual check of events and bets: {}",
+            orphaned.len(),
+            fork_point,
+            orphaned.join(", ")
+        );
+        error!("🚨 {}", message);
+        if let Some(alerter) = &ctx.alerter {
+            alerter.notice("reorg_orphaned_resolutions", &message);
+        }
+    }
+
+    sqlx::query("DELETE FROM recent_blocks WHERE block_number > $1")
+        .bind(fork_point as i64)
+        .execute(&ctx.control_pool)
+        .await?;
+    sqlx::query("DELETE FROM block_contents WHERE block_number > $1")
+        .bind(fork_point as i64)
+        .execute(&ctx.control_pool)
+        .await?;
+    // Page tokens into an orphaned block would resume into its old events.
+    sqlx::query("DELETE FROM page_cursors WHERE block_number > $1")
+        .bind(fork_point as i64)
+        .execute(&ctx.pool)
+        .await?;
+    ctx.headers.invalidate_from(fork_point + 1);
+
+    info!("⏪ Rolled back recent block window to {}", fork_point);
+    Ok(())
+}
+
+/// Stores `header` in the window and prunes blocks that fell out of it.
+/// Returns `Ok(false)` if the block doesn't build on the stored previous
+/// block, meaning the chain reorganized mid-pass.
+pub async fn record(ctx: &Context, header: &BlockHeader) -> Result<bool, sqlx::Error> {
+    let parent_hash: Option<String> =
+        sqlx::query_scalar("SELECT block_hash FROM recent_blocks WHERE block_number = $1")
+            .bind(header.block_number as i64 - 1)
+            .fetch_optional(&ctx.control_pool)
+            .await?;
+
+    if let Some(parent_hash) = parent_hash {
+        if parent_hash != hash_hex(&header.parent_hash) {
+            warn!(
+                "🍴 Block {} does not build on stored block {}",
+                header.block_number,
+                header.block_number - 1
+            );
+            return Ok(false);
+        }
+    }
+
+    sqlx::query(
+        "INSERT INTO recent_blocks (block_number, block_hash) VALUES ($1, $2)
+         ON CONFLICT (block_number) DO UPDATE SET block_hash = EXCLUDED.block_hash",
+    )
+    .bind(header.block_number as i64)
+    .bind(hash_hex(&header.block_hash))
+    .execute(&ctx.control_pool)
+    .await?;
+
+    sqlx::query("DELETE FROM recent_blocks WHERE block_number <= $1")
+        .bind(header.block_number as i64 - ctx.config.recent_blocks_depth as i64)
+        .execute(&ctx.control_pool)
+        .await?;
+
+    Ok(true)
+}
+
+/// Content-based alternative to `detect_fork`: refetches each contract's
+/// events for every block in the window, oldest first, and compares their
+/// digest with the one stored when the block was processed. Contents don't
+/// chain, so the whole window is refetched on every pass. Returns the block
+/// to roll back to when the oldest changed block is found.
+pub async fn detect_content_change(ctx: &Context) -> Option<u64> {
+    let stored: Vec<(i64, String, i64)> = match sqlx::query_as(
+        "SELECT block_number, contract_address, content_hash
+         FROM block_contents ORDER BY block_number, contract_address",
+    )
+    .fetch_all(&ctx.control_pool)
+    .await
+    {
+        Ok(stored) => stored,
+        Err(e) => {
+            error!("Failed to load recent block contents: {}", e);
+            return None;
+        }
+    };
+
+    for (block_number, contract_address, content_hash) in stored {
+        let block_number = block_number as u64;
+        let Ok(contract) = Felt::from_hex(&contract_address) else {
+            continue;
+        };
+
+        let filter = event_filter(ctx, contract, block_number, block_number);
+        let mut events = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let page = match fetch_events_page(ctx, &filter, continuation_token).await {
+                Ok(page) => page,
+                Err(e) => {
+                    error!("Failed to verify block {}: {}", block_number, e);
+                    return None;
+                }
+            };
+            events.extend(
+                page.events
+                    .into_iter()
+                    .filter(|event| event.block_number == Some(block_number)),
+            );
+            continuation_token = page.continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        if consistency::digest(&events) as i64 != content_hash {
+            warn!(
+                "🍴 Reorg detected: events of {} in block {} changed since it was processed",
+                contract_address, block_number
+            );
+            return Some(block_number - 1);
+        }
+    }
+    None
+}
+
+/// Stores each contract's event digest for `block_number` and prunes
+/// blocks that fell out of the window.
+pub async fn record_contents(
+    ctx: &Context,
+    block_number: u64,
+    digests: &[(Felt, u64)],
+) -> Result<(), sqlx::Error> {
+    for (contract, digest) in digests {
+        sqlx::query(
+            "INSERT INTO block_contents (block_number, contract_address, content_hash)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (block_number, contract_address)
+             DO UPDATE SET content_hash = EXCLUDED.content_hash",
+        )
+        .bind(block_number as i64)
+        .bind(contract.to_fixed_hex_string())
+        .bind(*digest as i64)
+        .execute(&ctx.control_pool)
+        .await?;
+    }
+
+    sqlx::query("DELETE FROM block_contents WHERE block_number <= $1")
+        .bind(block_number as i64 - ctx.config.content_hash_window as i64)
+        .execute(&ctx.control_pool)
+        .await?;
+    Ok(())
+}
+
+fn hash_hex(hash: &Felt) -> String {
+    format_address(&hash.to_fixed_hex_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolution(block_number: u64) -> EventTimeout {
+        EventTimeout {
+            event_address: format!("0x{}", block_number),
+            event_outcome: 1,
+            timestamp: 0,
+            transaction_hash: Felt::from(block_number),
+            block_number: Some(block_number),
+            resolved_by: None,
+            market_id: None,
+        }
+    }
+
+    #[test]
+    fn rollback_names_only_resolutions_after_the_fork_point() {
+        let recent = RecentResolutions::default();
+        for block_number in [10, 11, 12, 13] {
+            recent.record(&resolution(block_number), 3);
+        }
+
+        let orphaned = recent.orphaned(11);
+        assert_eq!(orphaned.len(), 2);
+        assert!(orphaned[0].starts_with("0x12 (outcome 1, tx "));
+        assert!(orphaned[1].starts_with("0x13 "));
+        // Block 10 fell out of the window, and 12 and 13 were taken.
+        assert_eq!(recent.orphaned(0), vec![recent_description(11)]);
+    }
+
+    fn recent_description(block_number: u64) -> String {
+        format!(
+            "0x{} (outcome 1, tx {})",
+            block_number,
+            Felt::from(block_number).to_fixed_hex_string()
+        )
+    }
+}
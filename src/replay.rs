@@ -0,0 +1,92 @@
+use crate::{webhook, Context};
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use sqlx::Row;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Re-sends every already-resolved event over the configured notification
+/// channels, at most `REPLAY_RATE_PER_SEC` a second, so a consumer that was
+/// offline or newly connected can rebuild its state. Payloads carry
+/// `"replay": true` and only the state stored in `events`, since the
+/// resolving transaction isn't kept.
+pub async fn run(ctx: &Context) {
+    if ctx.resolution_webhook.is_none() && !ctx.emit_stdout {
+        warn!("⚠️ REPLAY_ON_STARTUP is set but no notification channel is configured");
+        return;
+    }
+
+    let market_id = match ctx.config.market_identity.market_id() {
+        Some(market_id) => format!("{}::TEXT", market_id.events_column),
+        None => "NULL::TEXT".to_string(),
+    };
+    let rows = sqlx::query(&format!(
+        "SELECT address, {}::TEXT AS outcome, {} AS market_id
+         FROM events WHERE {} ORDER BY address",
+        ctx.config.outcome_column,
+        market_id,
+        ctx.config.resolution_model.resolved_filter()
+    ))
+    .fetch_all(&ctx.pool)
+    .await;
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            error!("Failed to load resolved events for replay: {}", e);
+            return;
+        }
+    };
+
+    info!(
+        "🔁 Replaying {} resolved event(s) at up to {}/s",
+        rows.len(),
+        ctx.config.replay_rate
+    );
+    let mut pace = tokio::time::interval(Duration::from_secs(1) / ctx.config.replay_rate);
+    let mut replayed = 0;
+    for row in &rows {
+        if ctx.session.shutdown_requested() {
+            warn!("🛑 Shutdown requested, stopping replay after {}", replayed);
+            return;
+        }
+        pace.tick().await;
+
+        let payload = payload(row.get("address"), row.get("outcome"), row.get("market_id"));
+        if let Some(webhook) = &ctx.resolution_webhook {
+            if let Err(e) = webhook::replay(webhook, &ctx.pool, &payload).await {
+                error!("Failed to replay {}: {}", payload["event_address"], e);
+                continue;
+            }
+        }
+        if ctx.emit_stdout {
+            let mut stdout = io::stdout().lock();
+            if let Err(e) = writeln!(stdout, "{}", payload).and_then(|()| stdout.flush()) {
+                error!("Failed to write replay to stdout: {}", e);
+            }
+        }
+        replayed += 1;
+    }
+
+    info!("🔁 Replayed {} resolved event(s)", replayed);
+}
+
+fn payload(address: String, outcome: Option<String>, market_id: Option<String>) -> Value {
+    // Outcomes are stored as text in some schemas; keep them numeric where
+    // they parse, like fresh notifications.
+    let outcome = match outcome {
+        Some(outcome) => match outcome.parse::<u64>() {
+            Ok(number) => json!(number),
+            Err(_) => json!(outcome),
+        },
+        None => Value::Null,
+    };
+    let mut payload = json!({
+        "event_address": address,
+        "outcome": outcome,
+        "replay": true,
+    });
+    if let Some(market_id) = market_id {
+        payload["market_id"] = json!(market_id);
+    }
+    payload
+}
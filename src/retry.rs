@@ -0,0 +1,53 @@
+use log::warn;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Exponential backoff for retrying a failing step within the same pass.
+pub struct Backoff {
+    remaining: u32,
+    delay: Duration,
+}
+
+impl Backoff {
+    pub fn new(retries: u32, initial_delay: Duration) -> Self {
+        Backoff {
+            remaining: retries,
+            delay: initial_delay,
+        }
+    }
+
+    /// Sleeps before the next attempt, or returns `false` once retries are
+    /// exhausted.
+    pub async fn retry(&mut self) -> bool {
+        if self.remaining == 0 {
+            return false;
+        }
+
+        self.remaining -= 1;
+        warn!(
+            "🔁 Retrying in {:?} ({} retries left)",
+            self.delay, self.remaining
+        );
+        tokio::time::sleep(self.delay).await;
+        self.delay *= 2;
+        true
+    }
+}
+
+/// SQLSTATE 40001 (serialization_failure) or 40P01 (deadlock_detected).
+pub fn is_serialization_failure(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(db_error) => {
+            matches!(db_error.code().as_deref(), Some("40001") | Some("40P01"))
+        }
+        _ => false,
+    }
+}
+
+/// `base` plus up to `base` again of random jitter, so concurrent writers
+/// that collided don't retry in lockstep.
+pub fn jittered(base: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    base + base.mul_f64((random % 1000) as f64 / 1000.0)
+}
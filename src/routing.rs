@@ -0,0 +1,193 @@
+use crate::config::is_identifier;
+use crate::format_address;
+use log::info;
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use starknet::core::types::{EmittedEvent, Felt};
+use starknet::core::utils::get_selector_from_name;
+use std::fs;
+
+/// Copies an event's data fields straight into a table, for events simple
+/// enough not to need a code-based handler.
+///
+/// ```json
+/// [{
+///   "event": "BetPlaced",
+///   "table": "bet_placed_events",
+///   "transaction_hash_column": "tx_hash",
+///   "columns": [
+///     { "column": "event_address", "index": 0, "type": "address" },
+///     { "column": "amount", "index": 2, "type": "integer" }
+///   ]
+/// }]
+/// ```
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Route {
+    pub event: String,
+    pub table: String,
+    #[serde(default)]
+    pub block_number_column: Option<String>,
+    #[serde(default)]
+    pub transaction_hash_column: Option<String>,
+    pub columns: Vec<ColumnMapping>,
+    #[serde(skip, default = "zero")]
+    pub selector: Felt,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ColumnMapping {
+    pub column: String,
+    /// Position in the event's `data` array.
+    pub index: usize,
+    #[serde(default, rename = "type")]
+    pub kind: ColumnKind,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnKind {
+    /// Zero-padded 0x address, matching `events.address`.
+    Address,
+    /// Raw hex string.
+    #[default]
+    Hex,
+    /// Value that fits in a BIGINT.
+    Integer,
+}
+
+fn zero() -> Felt {
+    Felt::ZERO
+}
+
+enum Value {
+    Text(String),
+    Integer(i64),
+}
+
+/// Loads and checks the routing file named by `ROUTING_CONFIG`.
+pub fn load(path: &str) -> Vec<Route> {
+    let contents = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read routing config {}: {}", path, e));
+    let mut routes: Vec<Route> = serde_json::from_str(&contents)
+        .unwrap_or_else(|e| panic!("Invalid routing config {}: {}", path, e));
+
+    for route in &mut routes {
+        route.selector = get_selector_from_name(&route.event)
+            .unwrap_or_else(|e| panic!("Invalid event name '{}': {}", route.event, e));
+
+        let identifiers = std::iter::once(&route.table)
+            .chain(route.block_number_column.iter())
+            .chain(route.transaction_hash_column.iter())
+            .chain(route.columns.iter().map(|mapping| &mapping.column));
+        for identifier in identifiers {
+            if !is_identifier(identifier) {
+                panic!(
+                    "Invalid identifier '{}' in route for {}",
+                    identifier, route.event
+                );
+            }
+        }
+
+        info!(
+            "🧭 Routing {} (selector {}) into {}",
+            route.event,
+            route.selector.to_fixed_hex_string(),
+            route.table
+        );
+    }
+
+    routes
+}
+
+/// Fails fast if a route targets a table or column that doesn't exist.
+pub async fn validate(pool: &Pool<Postgres>, routes: &[Route]) {
+    for route in routes {
+        let columns = route
+            .block_number_column
+            .iter()
+            .chain(route.transaction_hash_column.iter())
+            .chain(route.columns.iter().map(|mapping| &mapping.column));
+
+        for column in columns {
+            let exists = sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS (
+                    SELECT 1 FROM information_schema.columns
+                    WHERE table_name = $1 AND column_name = $2
+                )",
+            )
+            .bind(&route.table)
+            .bind(column)
+            .fetch_one(pool)
+            .await
+            .expect("Failed to validate routing config");
+
+            if !exists {
+                panic!(
+                    "Route for {} targets missing column {}.{}",
+                    route.event, route.table, column
+                );
+            }
+        }
+    }
+}
+
+pub fn find<'a>(routes: &'a [Route], event: &EmittedEvent) -> Option<&'a Route> {
+    let selector = event.keys.first()?;
+    routes.iter().find(|route| route.selector == *selector)
+}
+
+/// Inserts one row for `event`. Returns `Ok(false)` when the event lacks a
+/// mapped data field. Rows are inserted with `ON CONFLICT DO NOTHING`, so a
+/// unique constraint on the target table makes replays idempotent.
+pub async fn write(
+    pool: &Pool<Postgres>,
+    route: &Route,
+    event: &EmittedEvent,
+) -> Result<bool, sqlx::Error> {
+    let mut columns = Vec::new();
+    let mut values = Vec::new();
+
+    for mapping in &route.columns {
+        let Some(raw) = event.data.get(mapping.index) else {
+            return Ok(false);
+        };
+        let value = match mapping.kind {
+            ColumnKind::Address => Value::Text(format_address(&raw.to_fixed_hex_string())),
+            ColumnKind::Hex => Value::Text(raw.to_hex_string()),
+            ColumnKind::Integer => match raw.to_i64() {
+                Some(value) => Value::Integer(value),
+                None => return Ok(false),
+            },
+        };
+        columns.push(mapping.column.as_str());
+        values.push(value);
+    }
+    if let Some(column) = &route.block_number_column {
+        columns.push(column);
+        values.push(Value::Integer(event.block_number.unwrap_or_default() as i64));
+    }
+    if let Some(column) = &route.transaction_hash_column {
+        columns.push(column);
+        values.push(Value::Text(event.transaction_hash.to_fixed_hex_string()));
+    }
+
+    let placeholders: Vec<String> = (1..=values.len()).map(|i| format!("${}", i)).collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT DO NOTHING",
+        route.table,
+        columns.join(", "),
+        placeholders.join(", ")
+    );
+
+    let mut query = sqlx::query(&sql);
+    for value in values {
+        query = match value {
+            Value::Text(text) => query.bind(text),
+            Value::Integer(integer) => query.bind(integer),
+        };
+    }
+    query.execute(pool).await?;
+
+    Ok(true)
+}
@@ -0,0 +1,124 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use starknet::core::types::{EventFilter, EventFilterWithPage, EventsPage, ResultPageRequest};
+use std::fmt;
+
+/// Sends several `starknet_getEvents` calls as one JSON-RPC batch request.
+/// `JsonRpcClient` only issues single calls, so this talks to the endpoint
+/// directly.
+pub struct BatchClient {
+    url: String,
+    client: reqwest::Client,
+    max_size: usize,
+}
+
+#[derive(Debug)]
+pub enum BatchError {
+    Http(reqwest::Error),
+    /// The endpoint doesn't support batches or returned something else.
+    Malformed(String),
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::Http(e) => write!(f, "batch request failed: {}", e),
+            BatchError::Malformed(reason) => write!(f, "unusable batch response: {}", reason),
+        }
+    }
+}
+
+impl From<reqwest::Error> for BatchError {
+    fn from(e: reqwest::Error) -> Self {
+        BatchError::Http(e)
+    }
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    id: usize,
+    result: Option<EventsPage>,
+    error: Option<Value>,
+}
+
+impl BatchClient {
+    pub fn new(url: String, max_size: usize) -> Self {
+        BatchClient {
+            url,
+            client: reqwest::Client::new(),
+            max_size,
+        }
+    }
+
+    /// Fetches the first page for each filter, in order, splitting into
+    /// requests of at most `max_size` calls. Any failure fails the whole
+    /// call so the caller can fall back to individual requests.
+    pub async fn first_pages(
+        &self,
+        filters: &[EventFilter],
+        chunk_size: u64,
+    ) -> Result<Vec<EventsPage>, BatchError> {
+        let mut pages = Vec::with_capacity(filters.len());
+        for chunk in filters.chunks(self.max_size) {
+            pages.extend(self.send(chunk, chunk_size).await?);
+        }
+        Ok(pages)
+    }
+
+    async fn send(
+        &self,
+        filters: &[EventFilter],
+        chunk_size: u64,
+    ) -> Result<Vec<EventsPage>, BatchError> {
+        let requests: Vec<Value> = filters
+            .iter()
+            .enumerate()
+            .map(|(id, filter)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": "starknet_getEvents",
+                    "params": {
+                        "filter": EventFilterWithPage {
+                            event_filter: filter.clone(),
+                            result_page_request: ResultPageRequest {
+                                continuation_token: None,
+                                chunk_size,
+                            },
+                        },
+                    },
+                })
+            })
+            .collect();
+
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&requests)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+
+        let mut responses: Vec<BatchResponse> =
+            serde_json::from_value(response).map_err(|e| BatchError::Malformed(e.to_string()))?;
+        if responses.len() != filters.len() {
+            return Err(BatchError::Malformed(format!(
+                "expected {} responses, got {}",
+                filters.len(),
+                responses.len()
+            )));
+        }
+        responses.sort_by_key(|response| response.id);
+
+        responses
+            .into_iter()
+            .map(|response| match (response.result, response.error) {
+                (Some(page), None) => Ok(page),
+                (_, Some(error)) => Err(BatchError::Malformed(error.to_string())),
+                (None, None) => Err(BatchError::Malformed("empty response".to_string())),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,78 @@
+use crate::Context;
+use log::{error, warn};
+use starknet::core::types::{DeclareTransaction, Felt, InvokeTransaction, Transaction};
+use starknet::providers::Provider;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const CACHE_SIZE: usize = 4096;
+
+/// Senders by transaction hash, since one transaction often resolves
+/// several events.
+#[derive(Default)]
+pub struct SenderCache {
+    senders: Mutex<HashMap<Felt, Option<Felt>>>,
+}
+
+/// Returns the account that sent `transaction_hash`, or `None` for
+/// transactions without a single sender (L1 handlers, deploys) or when the
+/// lookup fails.
+pub async fn sender(ctx: &Context, transaction_hash: Felt) -> Option<Felt> {
+    if let Some(cached) = ctx.senders.senders.lock().unwrap().get(&transaction_hash) {
+        return *cached;
+    }
+
+    let transaction = match ctx.provider.get_transaction_by_hash(transaction_hash).await {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            // Not cached, so a replay of the block looks it up again.
+            error!(
+                "Failed to fetch transaction {}: {}",
+                transaction_hash.to_fixed_hex_string(),
+                e
+            );
+            return None;
+        }
+    };
+
+    let sender = match &transaction {
+        Transaction::Invoke(InvokeTransaction::V0(tx)) => Some(tx.contract_address),
+        Transaction::Invoke(InvokeTransaction::V1(tx)) => Some(tx.sender_address),
+        Transaction::Invoke(InvokeTransaction::V3(tx)) => Some(tx.sender_address),
+        Transaction::Declare(DeclareTransaction::V0(tx)) => Some(tx.sender_address),
+        Transaction::Declare(DeclareTransaction::V1(tx)) => Some(tx.sender_address),
+        Transaction::Declare(DeclareTransaction::V2(tx)) => Some(tx.sender_address),
+        Transaction::Declare(DeclareTransaction::V3(tx)) => Some(tx.sender_address),
+        Transaction::L1Handler(_) | Transaction::Deploy(_) | Transaction::DeployAccount(_) => {
+            warn!(
+                "Transaction {} has no single sender, leaving resolved_by empty",
+                transaction_hash.to_fixed_hex_string()
+            );
+            None
+        }
+    };
+
+    let mut senders = ctx.senders.senders.lock().unwrap();
+    if senders.len() >= CACHE_SIZE {
+        senders.clear();
+    }
+    senders.insert(transaction_hash, sender);
+    sender
+}
+
+impl SenderCache {
+    /// Cached senders, for a restart snapshot.
+    pub fn entries(&self) -> Vec<(Felt, Option<Felt>)> {
+        let senders = self.senders.lock().unwrap();
+        senders
+            .iter()
+            .map(|(hash, sender)| (*hash, *sender))
+            .collect()
+    }
+
+    /// Seeds the cache from a restart snapshot.
+    pub fn restore(&self, restored: Vec<(Felt, Option<Felt>)>) {
+        let mut senders = self.senders.lock().unwrap();
+        senders.extend(restored.into_iter().take(CACHE_SIZE));
+    }
+}
@@ -0,0 +1,98 @@
+use log::info;
+use serde_json::json;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Notify;
+
+/// Totals for this run, reported once it ends, and the shutdown request
+/// that ends it.
+pub struct Session {
+    started: Instant,
+    blocks_processed: AtomicU64,
+    events_applied: AtomicU64,
+    errors: AtomicU64,
+    shutdown: Arc<Shutdown>,
+}
+
+#[derive(Default)]
+struct Shutdown {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            started: Instant::now(),
+            blocks_processed: AtomicU64::new(0),
+            events_applied: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            shutdown: Arc::default(),
+        }
+    }
+
+    pub fn add_blocks(&self, count: u64) {
+        self.blocks_processed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_events(&self, count: u64) {
+        self.events_applied.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Turns SIGINT and SIGTERM into a shutdown request, so the current pass
+    /// finishes before the process exits.
+    pub fn listen_for_shutdown(&self) {
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            let mut terminate =
+                signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            info!("🛑 Shutdown requested, stopping after the current pass");
+            shutdown.requested.store(true, Ordering::Relaxed);
+            shutdown.notify.notify_waiters();
+        });
+    }
+
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown.requested.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once shutdown has been requested.
+    pub async fn wait_for_shutdown(&self) {
+        loop {
+            let notified = self.shutdown.notify.notified();
+            if self.shutdown_requested() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Logs the session summary and, with `to_stdout`, also prints it as a
+    /// JSON line.
+    pub fn report(&self, final_cursor: u64, to_stdout: bool) {
+        let summary = json!({
+            "blocks_processed": self.blocks_processed.load(Ordering::Relaxed),
+            "events_applied": self.events_applied.load(Ordering::Relaxed),
+            "errors": self.errors.load(Ordering::Relaxed),
+            "final_cursor": final_cursor,
+            "uptime_secs": self.started.elapsed().as_secs(),
+        });
+        info!("📋 Session summary: {}", summary);
+
+        if to_stdout {
+            let mut stdout = io::stdout().lock();
+            let _ = writeln!(stdout, "{}", summary).and_then(|()| stdout.flush());
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::config::Config;
+use crate::retry::Backoff;
+use log::{error, info, warn};
+use sqlx::{Pool, Postgres};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A tunable value seeded from the environment, which a row of
+/// `indexer_settings` can override at runtime.
+struct Setting {
+    name: &'static str,
+    value: AtomicU64,
+    default: u64,
+    min: u64,
+}
+
+impl Setting {
+    fn new(name: &'static str, default: u64, min: u64) -> Self {
+        Setting {
+            name,
+            value: AtomicU64::new(default),
+            default,
+            min,
+        }
+    }
+
+    fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Settings that can change between passes without a redeploy. Everything
+/// else stays in `Config` and is only read from the environment.
+pub struct Settings {
+    batch_commit_size: Setting,
+    /// `0` disables block-span commits.
+    batch_commit_blocks: Setting,
+    in_pass_retries: Setting,
+    in_pass_retry_backoff_ms: Setting,
+    refresh_interval: Option<Duration>,
+    last_refresh: Mutex<Option<Instant>>,
+}
+
+impl Settings {
+    pub fn new(config: &Config) -> Self {
+        Settings {
+            batch_commit_size: Setting::new(
+                "batch_commit_size",
+                config.batch_commit_size as u64,
+                1,
+            ),
+            batch_commit_blocks: Setting::new(
+                "batch_commit_blocks",
+                config.batch_commit_blocks.unwrap_or(0),
+                0,
+            ),
+            in_pass_retries: Setting::new("in_pass_retries", config.in_pass_retries as u64, 0),
+            in_pass_retry_backoff_ms: Setting::new(
+                "in_pass_retry_backoff_ms",
+                config.in_pass_retry_backoff.as_millis() as u64,
+                0,
+            ),
+            refresh_interval: config.settings_refresh_interval,
+            last_refresh: Mutex::new(None),
+        }
+    }
+
+    pub fn batch_commit_size(&self) -> usize {
+        self.batch_commit_size.get() as usize
+    }
+
+    pub fn batch_commit_blocks(&self) -> Option<u64> {
+        Some(self.batch_commit_blocks.get()).filter(|&n| n > 0)
+    }
+
+    pub fn in_pass_backoff(&self) -> Backoff {
+        Backoff::new(
+            self.in_pass_retries.get() as u32,
+            Duration::from_millis(self.in_pass_retry_backoff_ms.get()),
+        )
+    }
+
+    fn all(&self) -> [&Setting; 4] {
+        [
+            &self.batch_commit_size,
+            &self.batch_commit_blocks,
+            &self.in_pass_retries,
+            &self.in_pass_retry_backoff_ms,
+        ]
+    }
+
+    /// Re-reads `indexer_settings` once `SETTINGS_REFRESH_SECS` has passed
+    /// since the last read. Invalid values are logged and ignored, keeping
+    /// the current value.
+    pub async fn refresh_if_due(&self, pool: &Pool<Postgres>) {
+        let Some(interval) = self.refresh_interval else {
+            return;
+        };
+        {
+            let mut last_refresh = self.last_refresh.lock().unwrap();
+            if matches!(*last_refresh, Some(at) if at.elapsed() < interval) {
+                return;
+            }
+            *last_refresh = Some(Instant::now());
+        }
+
+        let rows: Vec<(String, String)> =
+            match sqlx::query_as("SELECT name, value FROM indexer_settings")
+                .fetch_all(pool)
+                .await
+            {
+                Ok(rows) => rows,
+                Err(e) => {
+                    error!("Failed to read indexer_settings: {}", e);
+                    return;
+                }
+            };
+
+        for (name, _) in &rows {
+            if !self.all().iter().any(|setting| setting.name == name) {
+                warn!("⚠️ Ignoring unknown indexer setting '{}'", name);
+            }
+        }
+
+        for setting in self.all() {
+            let value = match rows.iter().find(|(name, _)| name == setting.name) {
+                None => setting.default,
+                Some((_, raw)) => match raw.trim().parse::<u64>() {
+                    Ok(value) if value >= setting.min => value,
+                    _ => {
+                        warn!(
+                            "⚠️ Ignoring invalid value '{}' for indexer setting '{}' (minimum {})",
+                            raw, setting.name, setting.min
+                        );
+                        continue;
+                    }
+                },
+            };
+
+            let previous = setting.value.swap(value, Ordering::Relaxed);
+            if previous != value {
+                info!(
+                    "⚙️ Setting {} changed from {} to {}",
+                    setting.name, previous, value
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use log::{info, warn};
+use starknet::core::types::{EmittedEvent, EventFilter};
+use starknet::providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider};
+
+/// Comparable form of an event: transaction hash, keys and data as hex.
+type EventFingerprint = (String, Vec<String>, Vec<String>);
+
+/// Fetches the same events from the shadow RPC and logs any discrepancy with
+/// what the primary RPC returned. The primary result is always the one
+/// applied to the database. `primary_events` only hold events from
+/// `block_number`, so shadow events from any other block are dropped the
+/// same way before comparing. Returns whether a discrepancy was found.
+pub async fn compare_events(
+    shadow_provider: &JsonRpcClient<HttpTransport>,
+    filter: EventFilter,
+    block_number: u64,
+    chunk_size: u64,
+    primary_events: &[EmittedEvent],
+) -> bool {
+    let address = filter.address;
+
+    let mut shadow_events = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        match shadow_provider
+            .get_events(filter.clone(), continuation_token, chunk_size)
+            .await
+        {
+            Ok(page) => {
+                shadow_events.extend(page.events);
+                continuation_token = page.continuation_token;
+            }
+            Err(err) => {
+                warn!("🕵️ Shadow RPC failed to fetch events: {}", err);
+                return false;
+            }
+        }
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+
+    shadow_events.retain(|event| event.block_number == Some(block_number));
+
+    let primary = fingerprints(primary_events);
+    let shadow = fingerprints(&shadow_events);
+
+    if primary == shadow {
+        info!("🕵️ Shadow RPC agrees ({} events)", primary.len());
+        return false;
+    }
+
+    warn!(
+        "🕵️ Shadow RPC discrepancy for {:?} in block {}: primary returned {} events, shadow returned {}",
+        address,
+        block_number,
+        primary.len(),
+        shadow.len()
+    );
+    for missing in primary.iter().filter(|event| !shadow.contains(event)) {
+        warn!("🕵️ Only on primary: {:?}", missing);
+    }
+    for extra in shadow.iter().filter(|event| !primary.contains(event)) {
+        warn!("🕵️ Only on shadow: {:?}", extra);
+    }
+    true
+}
+
+fn fingerprints(events: &[EmittedEvent]) -> Vec<EventFingerprint> {
+    let mut fingerprints: Vec<EventFingerprint> = events
+        .iter()
+        .map(|event| {
+            (
+                event.transaction_hash.to_hex_string(),
+                event.keys.iter().map(|key| key.to_hex_string()).collect(),
+                event
+                    .data
+                    .iter()
+                    .map(|value| value.to_hex_string())
+                    .collect(),
+            )
+        })
+        .collect();
+    fingerprints.sort();
+    fingerprints
+}
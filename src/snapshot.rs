@@ -0,0 +1,194 @@
+use crate::blocks::BlockHeader;
+use crate::{get_last_processed_block, indexed_events, indexer_version, Context};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use starknet::core::types::Felt;
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bumped whenever the snapshot layout changes.
+const SNAPSHOT_FORMAT: u32 = 1;
+
+/// In-memory caches written on shutdown so the next start doesn't refetch
+/// them. Felts are stored as hex.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    format: u32,
+    version: String,
+    saved_at: u64,
+    /// Cursor when the snapshot was taken; it only applies to that cursor.
+    cursor: u64,
+    selectors: Vec<String>,
+    headers: Vec<HeaderRecord>,
+    senders: Vec<(String, Option<String>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HeaderRecord {
+    block_number: u64,
+    block_hash: String,
+    parent_hash: String,
+    timestamp: u64,
+    transactions: Vec<String>,
+    accepted_on_l1: bool,
+}
+
+impl HeaderRecord {
+    fn new(header: &BlockHeader) -> Self {
+        HeaderRecord {
+            block_number: header.block_number,
+            block_hash: header.block_hash.to_fixed_hex_string(),
+            parent_hash: header.parent_hash.to_fixed_hex_string(),
+            timestamp: header.timestamp,
+            transactions: header
+                .transactions
+                .iter()
+                .map(Felt::to_fixed_hex_string)
+                .collect(),
+            accepted_on_l1: header.accepted_on_l1,
+        }
+    }
+
+    fn header(&self) -> Option<BlockHeader> {
+        Some(BlockHeader {
+            block_number: self.block_number,
+            block_hash: Felt::from_hex(&self.block_hash).ok()?,
+            parent_hash: Felt::from_hex(&self.parent_hash).ok()?,
+            timestamp: self.timestamp,
+            transactions: self
+                .transactions
+                .iter()
+                .map(|hash| Felt::from_hex(hash).ok())
+                .collect::<Option<_>>()?,
+            accepted_on_l1: self.accepted_on_l1,
+        })
+    }
+}
+
+fn selectors(ctx: &Context) -> Vec<String> {
+    indexed_events(ctx)
+        .into_iter()
+        .map(|(_, selector)| selector.to_fixed_hex_string())
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Writes the header and sender caches to `path`. Only headers of blocks
+/// up to the cursor are kept, since later ones may not survive a reorg
+/// check.
+pub async fn save(ctx: &Context, path: &str) {
+    let cursor = get_last_processed_block(&ctx.control_pool).await;
+    let snapshot = Snapshot {
+        format: SNAPSHOT_FORMAT,
+        version: indexer_version(),
+        saved_at: now(),
+        cursor,
+        selectors: selectors(ctx),
+        headers: ctx
+            .headers
+            .entries(cursor)
+            .iter()
+            .map(HeaderRecord::new)
+            .collect(),
+        senders: ctx
+            .senders
+            .entries()
+            .into_iter()
+            .map(|(hash, sender)| {
+                (
+                    hash.to_fixed_hex_string(),
+                    sender.map(|sender| sender.to_fixed_hex_string()),
+                )
+            })
+            .collect(),
+    };
+
+    // Written beside the target and renamed, so a crash mid-write never
+    // leaves a truncated snapshot behind.
+    let temporary = format!("{}.tmp", path);
+    let result = serde_json::to_vec(&snapshot)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| fs::write(&temporary, bytes).map_err(|e| e.to_string()))
+        .and_then(|()| fs::rename(&temporary, path).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => info!(
+            "📸 Saved {} header(s) and {} sender(s) to {}",
+            snapshot.headers.len(),
+            snapshot.senders.len(),
+            path
+        ),
+        Err(e) => error!("Failed to write snapshot {}: {}", path, e),
+    }
+}
+
+/// Seeds the caches from `path` when the snapshot was taken by this version
+/// at the current cursor, with the same indexed events, and within
+/// `max_age`. Anything else leaves the caches to fill up from scratch.
+pub async fn load(ctx: &Context, path: &str, max_age: Duration) {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            warn!("⚠️ Failed to read snapshot {}, starting cold: {}", path, e);
+            return;
+        }
+    };
+    let snapshot: Snapshot = match serde_json::from_slice(&contents) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            warn!("⚠️ Unreadable snapshot {}, starting cold: {}", path, e);
+            return;
+        }
+    };
+
+    let cursor = get_last_processed_block(&ctx.control_pool).await;
+    let stale = if snapshot.format != SNAPSHOT_FORMAT || snapshot.version != indexer_version() {
+        Some(format!("it was written by {}", snapshot.version))
+    } else if now().saturating_sub(snapshot.saved_at) > max_age.as_secs() {
+        Some(format!("it is older than {}s", max_age.as_secs()))
+    } else if snapshot.cursor != cursor {
+        Some(format!(
+            "it was taken at block {}, the cursor is at {}",
+            snapshot.cursor, cursor
+        ))
+    } else if snapshot.selectors != selectors(ctx) {
+        Some("the indexed events changed".to_string())
+    } else {
+        None
+    };
+    if let Some(reason) = stale {
+        info!("📸 Ignoring snapshot {}: {}", path, reason);
+        return;
+    }
+
+    let headers: Vec<BlockHeader> = snapshot
+        .headers
+        .iter()
+        .filter_map(HeaderRecord::header)
+        .collect();
+    let senders: Vec<(Felt, Option<Felt>)> = snapshot
+        .senders
+        .iter()
+        .filter_map(|(hash, sender)| {
+            let sender = match sender {
+                Some(sender) => Some(Felt::from_hex(sender).ok()?),
+                None => None,
+            };
+            Some((Felt::from_hex(hash).ok()?, sender))
+        })
+        .collect();
+    info!(
+        "📸 Restored {} header(s) and {} sender(s) from {}",
+        headers.len(),
+        senders.len(),
+        path
+    );
+    ctx.headers.restore(headers);
+    ctx.senders.restore(senders);
+}
@@ -0,0 +1,229 @@
+use crate::config::is_identifier;
+use crate::{format_address, Context};
+use log::{error, info, warn};
+use num_traits::ToPrimitive;
+use serde::Deserialize;
+use starknet::core::types::{EmittedEvent, Felt};
+use starknet::core::utils::get_selector_from_name;
+use std::fs;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+/// Points buffered between the indexer and the writer task; further points
+/// are dropped while it's full.
+const QUEUE_SIZE: usize = 10_000;
+/// Longest a point waits before its batch is sent.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Turns one event type into time-series points: `contract` and `tx` tags
+/// with the emitting address and transaction, extra tags and integer fields
+/// from `data`, and the block timestamp unless `timestamp_index` names a
+/// data field. The `tx` tag keeps points from one block distinct and makes
+/// replays overwrite rather than duplicate them.
+///
+/// ```json
+/// [{
+///   "event": "BetPlaced",
+///   "measurement": "bets",
+///   "tags": [{ "name": "outcome", "index": 1 }],
+///   "fields": [{ "name": "amount", "index": 2 }]
+/// }]
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct Series {
+    pub event: String,
+    pub measurement: String,
+    #[serde(default)]
+    pub tags: Vec<DataField>,
+    pub fields: Vec<DataField>,
+    #[serde(default)]
+    pub timestamp_index: Option<usize>,
+    #[serde(skip, default = "zero")]
+    pub selector: Felt,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DataField {
+    pub name: String,
+    /// Position in the event's `data` array.
+    pub index: usize,
+}
+
+fn zero() -> Felt {
+    Felt::ZERO
+}
+
+/// Loads and checks the series file named by `TIMESERIES_CONFIG`.
+pub fn load(path: &str) -> Vec<Series> {
+    let contents = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read time-series config {}: {}", path, e));
+    let mut series: Vec<Series> = serde_json::from_str(&contents)
+        .unwrap_or_else(|e| panic!("Invalid time-series config {}: {}", path, e));
+
+    for entry in &mut series {
+        entry.selector = get_selector_from_name(&entry.event)
+            .unwrap_or_else(|e| panic!("Invalid event name '{}': {}", entry.event, e));
+
+        if entry.fields.is_empty() {
+            panic!("Series for {} needs at least one field", entry.event);
+        }
+        let names = std::iter::once(&entry.measurement)
+            .chain(entry.tags.iter().map(|tag| &tag.name))
+            .chain(entry.fields.iter().map(|field| &field.name));
+        for name in names {
+            if !is_identifier(name) {
+                panic!("Invalid name '{}' in series for {}", name, entry.event);
+            }
+        }
+
+        info!(
+            "📈 Writing {} (selector {}) to measurement {}",
+            entry.event,
+            entry.selector.to_fixed_hex_string(),
+            entry.measurement
+        );
+    }
+
+    series
+}
+
+pub fn find<'a>(series: &'a [Series], event: &EmittedEvent) -> Option<&'a Series> {
+    let selector = event.keys.first()?;
+    series.iter().find(|entry| entry.selector == *selector)
+}
+
+/// Sends points to an InfluxDB line-protocol endpoint in batches from a
+/// background task, so a slow or unavailable store never holds up the
+/// relational writes. Failed batches are logged and dropped.
+pub struct TimeseriesWriter {
+    queue: mpsc::Sender<String>,
+}
+
+impl TimeseriesWriter {
+    /// `url` is the full write endpoint, with `precision=s`, e.g.
+    /// `http://influx:8086/api/v2/write?org=o&bucket=b&precision=s`.
+    pub fn spawn(url: String, token: Option<String>, batch_size: usize) -> Self {
+        let (queue, mut pending) = mpsc::channel::<String>(QUEUE_SIZE);
+        let client = reqwest::Client::new();
+
+        tokio::spawn(async move {
+            let mut batch = Vec::with_capacity(batch_size);
+            // Counted from the first point of the batch, so a steady trickle
+            // still gets flushed.
+            let mut deadline = Instant::now();
+            loop {
+                let received = if batch.is_empty() {
+                    Ok(pending.recv().await)
+                } else {
+                    tokio::time::timeout_at(deadline, pending.recv()).await
+                };
+                let closed = match received {
+                    Ok(Some(line)) => {
+                        if batch.is_empty() {
+                            deadline = Instant::now() + FLUSH_INTERVAL;
+                        }
+                        batch.push(line);
+                        if batch.len() < batch_size {
+                            continue;
+                        }
+                        false
+                    }
+                    Ok(None) => true,
+                    Err(_) => false,
+                };
+
+                if !batch.is_empty() {
+                    let mut request = client.post(&url).body(batch.join("\n"));
+                    if let Some(token) = &token {
+                        request = request.header("Authorization", format!("Token {}", token));
+                    }
+                    let result = async { request.send().await?.error_for_status() }.await;
+                    if let Err(e) = result {
+                        error!("❌ Dropping {} time-series point(s): {}", batch.len(), e);
+                    }
+                    batch.clear();
+                }
+                if closed {
+                    return;
+                }
+            }
+        });
+
+        TimeseriesWriter { queue }
+    }
+}
+
+/// Queues a point for `event`. Events missing a mapped field are skipped.
+pub async fn record(ctx: &Context, series: &Series, event: &EmittedEvent) {
+    let Some(writer) = &ctx.timeseries_writer else {
+        return;
+    };
+
+    let timestamp = match series.timestamp_index {
+        Some(index) => event.data.get(index).and_then(|value| value.to_u64()),
+        None => match event.block_number {
+            Some(block_number) => match ctx.headers.get(&ctx.provider, block_number).await {
+                Ok(header) => header.map(|header| header.timestamp),
+                Err(e) => {
+                    warn!("Failed to fetch header for block {}: {}", block_number, e);
+                    None
+                }
+            },
+            None => None,
+        },
+    };
+    let Some(timestamp) = timestamp else {
+        warn!(
+            "⚠️ No timestamp for {} in tx {}, skipping point",
+            series.event,
+            event.transaction_hash.to_fixed_hex_string()
+        );
+        return;
+    };
+    let Some(line) = line(series, event, timestamp) else {
+        warn!(
+            "⚠️ {} in tx {} lacks a mapped field, skipping point",
+            series.event,
+            event.transaction_hash.to_fixed_hex_string()
+        );
+        return;
+    };
+
+    if writer.queue.try_send(line).is_err() {
+        warn!(
+            "⚠️ Time-series queue full, dropping a {} point",
+            series.event
+        );
+    }
+}
+
+/// Formats one point in InfluxDB line protocol. Field values that fit an
+/// `i64` are written as integers, larger ones as hex strings.
+fn line(series: &Series, event: &EmittedEvent, timestamp: u64) -> Option<String> {
+    let mut line = format!(
+        "{},contract={},tx={}",
+        series.measurement,
+        format_address(&event.from_address.to_fixed_hex_string()),
+        event.transaction_hash.to_fixed_hex_string()
+    );
+    for tag in &series.tags {
+        let value = event.data.get(tag.index)?;
+        line.push_str(&format!(",{}={}", tag.name, value.to_hex_string()));
+    }
+
+    let fields: Option<Vec<String>> = series
+        .fields
+        .iter()
+        .map(|field| {
+            let value = event.data.get(field.index)?;
+            Some(match value.to_i64() {
+                Some(integer) => format!("{}={}i", field.name, integer),
+                None => format!("{}=\"{}\"", field.name, value.to_hex_string()),
+            })
+        })
+        .collect();
+    line.push_str(&format!(" {} {}", fields?.join(","), timestamp));
+
+    Some(line)
+}
@@ -0,0 +1,244 @@
+use crate::retry::Backoff;
+use crate::EventTimeout;
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use sqlx::postgres::PgConnection;
+use sqlx::{Pool, Postgres};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const SIGNATURE_HEADER: &str = "X-Trunks-Signature";
+const RETRIES: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Longest a delivery may take before it counts as failed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const OUTBOX_IDLE_DELAY: Duration = Duration::from_secs(1);
+/// How long a claimed outbox row is hidden from other deliveries. Longer
+/// than `REQUEST_TIMEOUT`, so a row is only picked up again once its
+/// instance has given up on it or died.
+const OUTBOX_LEASE_SECS: f64 = 60.0;
+/// Longest wait between two delivery attempts of an outbox row.
+const OUTBOX_MAX_BACKOFF_SECS: i32 = 300;
+
+/// Notifies `RESOLUTION_WEBHOOK_URL` of every committed resolution. By
+/// default payloads go through a bounded in-memory queue drained by a
+/// background task, so a slow endpoint never blocks indexing; when the queue
+/// is full new payloads are dropped. In outbox mode they are instead written
+/// to the `outbox` table with the resolution and retried until delivered.
+pub struct ResolutionWebhook {
+    queue: Option<mpsc::Sender<Vec<u8>>>,
+}
+
+#[derive(Clone)]
+struct Endpoint {
+    url: String,
+    secret: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Endpoint {
+    async fn post(&self, body: Vec<u8>) -> Result<(), reqwest::Error> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+
+        request.body(body).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+impl ResolutionWebhook {
+    /// With `outbox_pool`, drains the `outbox` table instead of an in-memory
+    /// queue.
+    pub fn spawn(
+        url: String,
+        secret: Option<String>,
+        queue_size: usize,
+        outbox_pool: Option<Pool<Postgres>>,
+    ) -> Self {
+        let endpoint = Endpoint {
+            url,
+            secret,
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Failed to build the webhook client"),
+        };
+
+        if let Some(pool) = outbox_pool {
+            info!("📮 Delivering resolution webhooks through the outbox table");
+            tokio::spawn(drain_outbox(pool, endpoint));
+            return ResolutionWebhook { queue: None };
+        }
+
+        let (queue, mut pending) = mpsc::channel::<Vec<u8>>(queue_size);
+        tokio::spawn(async move {
+            while let Some(body) = pending.recv().await {
+                let mut backoff = Backoff::new(RETRIES, RETRY_DELAY);
+                loop {
+                    match endpoint.post(body.clone()).await {
+                        Ok(()) => break,
+                        Err(e) => {
+                            error!("Resolution webhook failed: {}", e);
+                            if !backoff.retry().await {
+                                error!("❌ Dropping resolution webhook after {} retries", RETRIES);
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        ResolutionWebhook { queue: Some(queue) }
+    }
+
+    /// Queues a notification after commit. A no-op in outbox mode, where
+    /// `enqueue` already wrote it.
+    pub fn notify(&self, event: &EventTimeout) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+
+        let body = payload(event).to_string().into_bytes();
+        if queue.try_send(body).is_err() {
+            warn!(
+                "⚠️ Resolution webhook queue full, dropping notification for {}",
+                event.event_address
+            );
+        }
+    }
+}
+
+/// Writes the notification for `event` to the outbox, inside the
+/// resolution's transaction.
+pub async fn enqueue(event: &EventTimeout, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO outbox (payload) VALUES ($1::JSONB)")
+        .bind(payload(event).to_string())
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Sends a replayed resolution, waiting for queue space rather than
+/// dropping it. In outbox mode the payload is written to the outbox.
+pub async fn replay(
+    webhook: &ResolutionWebhook,
+    pool: &Pool<Postgres>,
+    payload: &Value,
+) -> Result<(), String> {
+    match &webhook.queue {
+        Some(queue) => queue
+            .send(payload.to_string().into_bytes())
+            .await
+            .map_err(|_| "webhook queue closed".to_string()),
+        None => sqlx::query("INSERT INTO outbox (payload) VALUES ($1::JSONB)")
+            .bind(payload.to_string())
+            .execute(pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+pub fn payload(event: &EventTimeout) -> Value {
+    let mut payload = json!({
+        "event_address": event.event_address,
+        "outcome": event.event_outcome,
+        "timestamp": event.timestamp,
+        "block_number": event.block_number,
+        "transaction_hash": event.transaction_hash.to_fixed_hex_string(),
+    });
+    if let Some(market_id) = &event.market_id {
+        payload["market_id"] = json!(market_id);
+    }
+    payload
+}
+
+/// Delivers outbox rows oldest first, one at a time. Each row is claimed
+/// with a short lease before it's posted, so several instances can drain
+/// the same table without holding a transaction open across the request;
+/// a row is only deleted once the webhook accepts it, and failures are
+/// retried with exponential backoff forever.
+async fn drain_outbox(pool: Pool<Postgres>, endpoint: Endpoint) {
+    loop {
+        match deliver_next(&pool, &endpoint).await {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => error!("Failed to drain outbox: {}", e),
+        }
+        tokio::time::sleep(OUTBOX_IDLE_DELAY).await;
+    }
+}
+
+/// Returns `Ok(false)` when no row is due.
+async fn deliver_next(pool: &Pool<Postgres>, endpoint: &Endpoint) -> Result<bool, sqlx::Error> {
+    let row: Option<(i64, String, i32)> = sqlx::query_as(
+        "UPDATE outbox SET next_attempt_at = now() + make_interval(secs => $1)
+         WHERE id = (
+             SELECT id FROM outbox
+             WHERE next_attempt_at <= now()
+             ORDER BY id
+             LIMIT 1
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING id, payload::TEXT, attempts",
+    )
+    .bind(OUTBOX_LEASE_SECS)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some((id, payload, attempts)) = row else {
+        return Ok(false);
+    };
+
+    match endpoint.post(payload.into_bytes()).await {
+        Ok(()) => {
+            sqlx::query("DELETE FROM outbox WHERE id = $1")
+                .bind(id)
+                .execute(pool)
+                .await?;
+        }
+        Err(e) => {
+            let delay = 2i32
+                .saturating_pow(attempts.clamp(0, 16) as u32)
+                .min(OUTBOX_MAX_BACKOFF_SECS);
+            warn!(
+                "⚠️ Outbox delivery {} failed (attempt {}), retrying in {}s: {}",
+                id,
+                attempts + 1,
+                delay,
+                e
+            );
+            sqlx::query(
+                "UPDATE outbox
+                 SET attempts = attempts + 1,
+                     last_error = $2,
+                     next_attempt_at = now() + make_interval(secs => $3)
+                 WHERE id = $1",
+            )
+            .bind(id)
+            .bind(e.to_string())
+            .bind(delay as f64)
+            .execute(pool)
+            .await?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// `sha256=<hex HMAC-SHA256 of the body>`, for receivers to check the
+/// payload came from us.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}